@@ -0,0 +1,14 @@
+//! Rustic backend helpers
+//!
+//! Functionality which works on the raw (i.e. not decrypted) repository backends
+//! given by [`rustic_core::RepositoryBackends`].
+
+pub(crate) mod appendonly;
+pub(crate) mod cache;
+pub(crate) mod datacache;
+pub(crate) mod hotcold;
+#[cfg(test)]
+pub(crate) mod mock;
+pub(crate) mod readonly;
+pub(crate) mod retry;
+pub(crate) mod throttle;
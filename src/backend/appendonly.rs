@@ -0,0 +1,210 @@
+//! Backend wrapper only allowing to add new files
+
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+
+use rustic_core::{
+    FileType, Id, ReadBackend, RepositoryBackends, RusticError, RusticResult, WriteBackend,
+};
+
+/// Wrap the repository backends such that only new files can be added
+///
+/// # Arguments
+///
+/// * `backends` - The repository backends
+pub(crate) fn append_only(backends: RepositoryBackends) -> RepositoryBackends {
+    let wrap = |be: Arc<dyn WriteBackend>| -> Arc<dyn WriteBackend> {
+        Arc::new(AppendOnlyBackend {
+            be,
+            existing: Mutex::default(),
+        })
+    };
+    RepositoryBackends::new(wrap(backends.repository()), backends.repo_hot().map(wrap))
+}
+
+/// A backend which allows to write new files, but neither removing nor overwriting files
+pub(crate) struct AppendOnlyBackend {
+    /// The wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// The existing files per file type, listed when writing the first file of that type
+    existing: Mutex<HashMap<&'static str, HashSet<Id>>>,
+}
+
+impl fmt::Debug for AppendOnlyBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppendOnlyBackend")
+            .field("be", &self.be.location())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AppendOnlyBackend {
+    /// The error returned for all rejected modifications
+    fn error(&self, action: &str) -> RusticError {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "cannot {action} in {}: repository is append-only",
+                self.be.location()
+            ),
+        )
+        .into()
+    }
+
+    /// Register a new file, failing if it already exists
+    ///
+    /// The file is registered before it is written, such that concurrent writes of the same file
+    /// are rejected. If the write fails, the file must be unregistered using [`Self::remove_added`].
+    fn add(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        let mut existing = self.existing.lock().unwrap();
+        let ids = match existing.entry(tpe.dirname()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(self.be.list(tpe)?.into_iter().collect()),
+        };
+        if !ids.insert(*id) {
+            return Err(self.error(&format!("overwrite {tpe:?} {id}")));
+        }
+        Ok(())
+    }
+
+    /// Unregister a file registered by [`Self::add`] which could not be written
+    fn remove_added(&self, tpe: FileType, id: &Id) {
+        if let Some(ids) = self.existing.lock().unwrap().get_mut(tpe.dirname()) {
+            _ = ids.remove(id);
+        }
+    }
+}
+
+impl ReadBackend for AppendOnlyBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.be.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for AppendOnlyBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        self.add(tpe, id)?;
+        let result = self.be.write_bytes(tpe, id, cacheable, buf);
+        if result.is_err() {
+            // allow to retry writing the file
+            self.remove_added(tpe, id);
+        }
+        result
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
+        Err(self.error(&format!("remove {tpe:?} {id}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::mock::MemoryBackend;
+
+    /// Get an id for tests
+    fn id(n: u8) -> Id {
+        Id::from_hex(&format!("{n:064x}")).unwrap()
+    }
+
+    #[test]
+    fn failed_write_can_be_retried() {
+        let mock = Arc::new(MemoryBackend::default());
+        let be = append_only(RepositoryBackends::new(mock.clone(), None)).repository();
+        be.write_bytes(FileType::Snapshot, &id(1), false, Bytes::from("1"))
+            .unwrap();
+
+        mock.fail_next(1);
+        assert!(be
+            .write_bytes(FileType::Snapshot, &id(2), false, Bytes::from("2"))
+            .is_err());
+        be.write_bytes(FileType::Snapshot, &id(2), false, Bytes::from("2"))
+            .unwrap();
+        assert_eq!(
+            be.read_full(FileType::Snapshot, &id(2)).unwrap(),
+            Bytes::from("2")
+        );
+    }
+
+    #[test]
+    fn new_files_are_added() {
+        let mock = Arc::new(MemoryBackend::default());
+        let be = append_only(RepositoryBackends::new(mock.clone(), None)).repository();
+        be.write_bytes(FileType::Pack, &id(1), false, Bytes::from("pack"))
+            .unwrap();
+
+        assert_eq!(mock.list(FileType::Pack).unwrap(), vec![id(1)]);
+        assert_eq!(
+            be.read_partial(FileType::Pack, &id(1), false, 0, 2)
+                .unwrap(),
+            Bytes::from("pa")
+        );
+    }
+
+    #[test]
+    fn removing_and_overwriting_is_refused() {
+        let mock = Arc::new(MemoryBackend::default());
+        mock.write_bytes(FileType::Snapshot, &id(1), false, Bytes::from("1"))
+            .unwrap();
+        let be = append_only(RepositoryBackends::new(mock.clone(), None)).repository();
+        be.write_bytes(FileType::Snapshot, &id(2), false, Bytes::from("2"))
+            .unwrap();
+
+        let errors = [
+            be.write_bytes(FileType::Snapshot, &id(1), false, Bytes::from("x"))
+                .unwrap_err(),
+            be.write_bytes(FileType::Snapshot, &id(2), false, Bytes::from("x"))
+                .unwrap_err(),
+            be.remove(FileType::Snapshot, &id(1), false).unwrap_err(),
+        ];
+        for err in errors {
+            assert!(err.to_string().contains("append-only"), "{err}");
+        }
+        assert_eq!(
+            mock.read_full(FileType::Snapshot, &id(1)).unwrap(),
+            Bytes::from("1")
+        );
+        assert_eq!(mock.list(FileType::Snapshot).unwrap(), vec![id(1), id(2)]);
+    }
+}
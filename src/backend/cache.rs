@@ -0,0 +1,67 @@
+//! Consistency helpers for the local cache
+//!
+//! The cache contains copies of the snapshot and index files of the repository. If the cache no
+//! longer matches the repository, e.g. after restoring a machine from a backup, cached files may
+//! be outdated or truncated. The functions in this module detect and remove such stale files.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use log::{debug, warn};
+
+use rustic_core::{FileType, ReadBackend, RepositoryBackends};
+
+/// File types which are cached and checked against the repository
+const CACHED_FILE_TYPES: [FileType; 2] = [FileType::Snapshot, FileType::Index];
+
+/// Remove all cached files which don't exist in the repository or differ in size
+///
+/// # Arguments
+///
+/// * `backends` - The repository backends
+/// * `cache_dir` - The cache directory of the repository
+///
+/// # Returns
+///
+/// The number of removed cache files
+pub(crate) fn remove_stale_cache_files(
+    backends: &RepositoryBackends,
+    cache_dir: &Path,
+) -> Result<usize> {
+    let be = backends.repository();
+    let mut count = 0;
+    for tpe in CACHED_FILE_TYPES {
+        let dir = cache_dir.join(tpe.dirname());
+        if !dir.is_dir() {
+            continue;
+        }
+        let sizes: HashMap<_, _> = be
+            .list_with_size(tpe)?
+            .into_iter()
+            .map(|(id, size)| (id.to_hex().to_string(), u64::from(size)))
+            .collect();
+
+        // cached files are stored in subdirectories named by the first two hex digits of the id
+        for subdir in fs::read_dir(&dir)? {
+            let subdir = subdir?.path();
+            if !subdir.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&subdir)? {
+                let file = file?;
+                let name = file.file_name().to_string_lossy().to_string();
+                let size = file.metadata()?.len();
+                if sizes.get(&name) == Some(&size) {
+                    continue;
+                }
+                debug!("removing stale cached {tpe:?} file {name}");
+                fs::remove_file(file.path())?;
+                count += 1;
+            }
+        }
+    }
+    if count > 0 {
+        warn!("removed {count} stale files from the cache which did not match the repository");
+    }
+    Ok(count)
+}
@@ -0,0 +1,356 @@
+//! Backend wrapper caching ranges of data packs
+//!
+//! Restoring many similar files reads the same blobs again and again. This wrapper saves all
+//! ranges read from data packs in a local directory, keyed by pack id, offset and length. As pack
+//! ids are content hashes, the cached ranges are never outdated. The ranges are stored as read
+//! from the backend, i.e. encrypted.
+//!
+//! The cached ranges are saved per repository. If their total size exceeds the maximum size, the
+//! least recently used ranges are removed.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use log::{debug, warn};
+
+use rustic_core::{FileType, Id, ReadBackend, RepositoryBackends, RusticResult, WriteBackend};
+
+/// Name of the directory within the cache directory containing the cached ranges
+pub(crate) const DATA_RANGES_DIR: &str = "data-ranges";
+
+/// The directory of the cached ranges of a repository
+///
+/// It is only known once the repository is opened, as it depends on the repository id. Until it is
+/// set, nothing is cached.
+pub(crate) type DataCacheDir = Arc<OnceLock<PathBuf>>;
+
+/// Counter to get unique names for temporary files
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Wrap the repository backends such that ranges of data packs are cached
+///
+/// # Arguments
+///
+/// * `backends` - The repository backends
+/// * `dir` - The directory to save the cached ranges in
+/// * `max_size` - The maximum total size of all cached ranges
+pub(crate) fn with_data_cache(
+    backends: RepositoryBackends,
+    dir: &DataCacheDir,
+    max_size: u64,
+) -> RepositoryBackends {
+    // the size is shared between all backends
+    let size = Arc::new(Mutex::new(None));
+    let wrap = |be: Arc<dyn WriteBackend>| -> Arc<dyn WriteBackend> {
+        Arc::new(DataCacheBackend {
+            be,
+            dir: dir.clone(),
+            max_size,
+            size: size.clone(),
+        })
+    };
+    RepositoryBackends::new(wrap(backends.repository()), backends.repo_hot().map(wrap))
+}
+
+/// A backend which caches ranges read from data packs of the wrapped backend
+pub(crate) struct DataCacheBackend {
+    /// The wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// The directory containing the cached ranges
+    dir: DataCacheDir,
+    /// The maximum total size of all cached ranges
+    max_size: u64,
+    /// The total size of all cached ranges, if already known
+    size: Arc<Mutex<Option<u64>>>,
+}
+
+impl fmt::Debug for DataCacheBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataCacheBackend")
+            .field("be", &self.be.location())
+            .field("dir", &self.dir.get())
+            .field("max_size", &self.max_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DataCacheBackend {
+    /// The path of a cached range
+    fn path(dir: &Path, id: &Id, offset: u32, length: u32) -> PathBuf {
+        let id = id.to_hex().to_string();
+        dir.join(&id[0..2])
+            .join(&id)
+            .join(format!("{offset}-{length}"))
+    }
+
+    /// Save a range in the cache; errors are only logged as the cache is optional
+    fn save(&self, dir: &Path, path: &Path, data: &[u8]) {
+        if let Err(err) = self.try_save(dir, path, data) {
+            warn!("error saving {path:?} in data cache: {err}");
+        }
+    }
+
+    /// Save a range in the cache and remove old ranges if the cache gets too large
+    ///
+    /// The range is written to a temporary file first, such that no partial range is used.
+    fn try_save(&self, dir: &Path, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let counter = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp = path.with_extension(format!("{}-{counter}.tmp", std::process::id()));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)?;
+
+        let mut size = self.size.lock().unwrap();
+        let total = match size.map(|size| size + data.len() as u64) {
+            Some(total) if total <= self.max_size => total,
+            // the size is not known yet or too large
+            _ => evict(dir, self.max_size)?,
+        };
+        *size = Some(total);
+        Ok(())
+    }
+}
+
+/// Remove the least recently used ranges if the total size exceeds the maximum size
+///
+/// Ranges are removed until the total size is below 3/4 of the maximum size, such that not every
+/// saved range leads to removing another one.
+///
+/// # Returns
+///
+/// The total size of the remaining ranges
+fn evict(dir: &Path, max_size: u64) -> std::io::Result<u64> {
+    let mut files = Vec::new();
+    for prefix in fs::read_dir(dir)? {
+        for pack in fs::read_dir(prefix?.path())? {
+            for range in fs::read_dir(pack?.path())? {
+                let range = range?;
+                let meta = range.metadata()?;
+                files.push((meta.modified()?, meta.len(), range.path()));
+            }
+        }
+    }
+    let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if size <= max_size {
+        return Ok(size);
+    }
+    files.sort_unstable();
+    for (_, len, path) in files {
+        if size <= max_size / 4 * 3 {
+            break;
+        }
+        debug!("removing cached range {path:?}");
+        fs::remove_file(&path)?;
+        size -= len;
+        // remove the directory of the pack if it is empty now
+        if let Some(parent) = path.parent() {
+            _ = fs::remove_dir(parent);
+        }
+    }
+    Ok(size)
+}
+
+impl ReadBackend for DataCacheBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.be.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        // tree packs are already cached by rustic_core
+        let Some(dir) = self
+            .dir
+            .get()
+            .filter(|_| !cacheable && tpe == FileType::Pack)
+        else {
+            return self.be.read_partial(tpe, id, cacheable, offset, length);
+        };
+        let path = Self::path(dir, id, offset, length);
+        match fs::read(&path) {
+            Ok(data) if data.len() == length as usize => {
+                debug!("using cached range {offset}-{length} of pack {id}");
+                // mark the range as recently used
+                if let Err(err) = fs::File::options()
+                    .append(true)
+                    .open(&path)
+                    .and_then(|file| file.set_modified(SystemTime::now()))
+                {
+                    debug!("error updating the time of {path:?}: {err}");
+                }
+                return Ok(data.into());
+            }
+            _ => {}
+        }
+        let data = self.be.read_partial(tpe, id, cacheable, offset, length)?;
+        self.save(dir, &path, &data);
+        Ok(data)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for DataCacheBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        self.be.write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::backend::mock::MemoryBackend;
+
+    /// Get an id for tests
+    fn id(n: u8) -> Id {
+        Id::from_hex(&format!("{n:064x}")).unwrap()
+    }
+
+    /// Get all files within the given directory
+    fn files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(self::files(&path));
+            } else {
+                files.push(path);
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn evict_removes_oldest_ranges_first() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let start = SystemTime::now() - Duration::from_secs(100);
+        // the ranges are written in a different order than they are used
+        let paths: Vec<_> = [2, 0, 3, 1]
+            .into_iter()
+            .map(|n| -> std::io::Result<_> {
+                let path = DataCacheBackend::path(dir.path(), &id(n), 0, 100);
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::write(&path, [0; 100])?;
+                fs::File::options()
+                    .append(true)
+                    .open(&path)?
+                    .set_modified(start + Duration::from_secs(n.into()))?;
+                Ok((n, path))
+            })
+            .collect::<std::io::Result<_>>()?;
+
+        // nothing is removed as long as the maximum size is not exceeded
+        assert_eq!(evict(dir.path(), 400)?, 400);
+        assert_eq!(files(dir.path()).len(), 4);
+
+        // ranges are removed until the size is below 3/4 of the maximum size
+        assert_eq!(evict(dir.path(), 300)?, 200);
+        for (n, path) in paths {
+            assert_eq!(path.exists(), n >= 2, "range of pack {n}");
+            // empty pack directories are removed
+            assert_eq!(path.parent().unwrap().exists(), n >= 2, "pack {n}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ranges_are_read_from_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock = Arc::new(MemoryBackend::default());
+        mock.write_bytes(FileType::Pack, &id(1), false, Bytes::from("0123456789"))
+            .unwrap();
+        let cache_dir = DataCacheDir::default();
+        let be = with_data_cache(RepositoryBackends::new(mock.clone(), None), &cache_dir, 100)
+            .repository();
+
+        // nothing is cached until the directory is known
+        _ = be
+            .read_partial(FileType::Pack, &id(1), false, 0, 4)
+            .unwrap();
+        assert!(files(dir.path()).is_empty());
+
+        cache_dir.set(dir.path().to_path_buf()).unwrap();
+        let calls = mock.calls();
+        for _ in 0..2 {
+            assert_eq!(
+                be.read_partial(FileType::Pack, &id(1), false, 2, 4)
+                    .unwrap(),
+                Bytes::from("2345")
+            );
+        }
+        assert_eq!(mock.calls(), calls + 1);
+        // the range was renamed from its temporary file
+        assert_eq!(
+            files(dir.path()),
+            vec![DataCacheBackend::path(dir.path(), &id(1), 2, 4)]
+        );
+
+        // cacheable packs are tree packs, which are not cached here
+        _ = be.read_partial(FileType::Pack, &id(1), true, 0, 4).unwrap();
+        assert_eq!(files(dir.path()).len(), 1);
+    }
+
+    #[test]
+    fn saving_ranges_evicts_old_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock = Arc::new(MemoryBackend::default());
+        mock.write_bytes(FileType::Pack, &id(1), false, Bytes::from("0123456789"))
+            .unwrap();
+        let cache_dir = DataCacheDir::new(OnceLock::from(dir.path().to_path_buf()));
+        let be = with_data_cache(RepositoryBackends::new(mock, None), &cache_dir, 8).repository();
+
+        for offset in 0..3 {
+            _ = be
+                .read_partial(FileType::Pack, &id(1), false, offset, 4)
+                .unwrap();
+        }
+        let files = files(dir.path());
+        assert!(files.len() < 3, "{files:?}");
+        assert!(files.contains(&DataCacheBackend::path(dir.path(), &id(1), 2, 4)));
+    }
+}
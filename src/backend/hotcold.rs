@@ -0,0 +1,118 @@
+//! Repair of hot/cold repositories used by `check --fix`
+//!
+//! rustic_core writes files first to the hot repository and then to the (cold) repository, while
+//! removing is done the other way round. If rustic is interrupted, the hot repository may
+//! therefore contain files which are no longer (or not yet) present in the cold repository. The
+//! repair plan in this module detects such orphaned hot files as well as files missing in the hot
+//! repository. As a concurrent backup writes to the hot repository first, orphaned files are only
+//! removed if the user confirms exclusive use of the repository.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use log::debug;
+
+use rustic_core::{FileType, Id, ReadBackend, WriteBackend};
+
+use crate::helpers::table_right_from;
+
+/// File types which are duplicated in the hot repository and need to be reconciled
+pub(crate) const HOT_FILE_TYPES: [FileType; 4] = [
+    FileType::Key,
+    FileType::Snapshot,
+    FileType::Index,
+    FileType::Pack,
+];
+
+/// A plan to repair inconsistencies between the hot and the cold repository
+#[derive(Debug, Default)]
+pub(crate) struct HotColdRepairPlan {
+    /// Files which are missing in the hot repository and need to be copied from the cold repository
+    pub(crate) missing: Vec<(FileType, Id)>,
+    /// Files which only exist in the hot repository and need to be removed
+    pub(crate) orphaned: Vec<(FileType, Id)>,
+}
+
+impl HotColdRepairPlan {
+    /// Create a repair plan by comparing the hot and the cold repository
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The cold repository backend
+    /// * `be_hot` - The hot repository backend
+    /// * `tree_packs` - The packs containing tree blobs which must be present in the hot repository
+    pub(crate) fn new(
+        be: &impl ReadBackend,
+        be_hot: &impl ReadBackend,
+        tree_packs: &BTreeSet<Id>,
+    ) -> Result<Self> {
+        let mut plan = Self::default();
+        for tpe in HOT_FILE_TYPES {
+            let hot_files: BTreeSet<_> = be_hot.list(tpe)?.into_iter().collect();
+            let files: BTreeSet<_> = be.list(tpe)?.into_iter().collect();
+
+            // only tree packs are saved in the hot repository
+            let needed = |id: &&Id| tpe != FileType::Pack || tree_packs.contains(id);
+            plan.missing.extend(
+                files
+                    .difference(&hot_files)
+                    .filter(needed)
+                    .map(|id| (tpe, *id)),
+            );
+            plan.orphaned
+                .extend(hot_files.difference(&files).map(|id| (tpe, *id)));
+        }
+        Ok(plan)
+    }
+
+    /// Returns whether there is nothing to repair
+    pub(crate) fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.orphaned.is_empty()
+    }
+
+    /// Print a summary of the repair plan
+    pub(crate) fn print(&self) {
+        let mut table = table_right_from(2, ["Action", "File type", "Count"]);
+        for tpe in HOT_FILE_TYPES {
+            let missing = self.missing.iter().filter(|(t, _)| *t == tpe).count();
+            if missing > 0 {
+                _ = table.add_row([
+                    "copy to hot repo".to_string(),
+                    format!("{tpe:?}"),
+                    missing.to_string(),
+                ]);
+            }
+            let orphaned = self.orphaned.iter().filter(|(t, _)| *t == tpe).count();
+            if orphaned > 0 {
+                _ = table.add_row([
+                    "remove from hot repo".to_string(),
+                    format!("{tpe:?}"),
+                    orphaned.to_string(),
+                ]);
+            }
+        }
+        println!("{table}");
+    }
+
+    /// Execute the repair plan
+    ///
+    /// Missing files are copied before orphaned files are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The cold repository backend
+    /// * `be_hot` - The hot repository backend
+    pub(crate) fn execute(&self, be: &impl ReadBackend, be_hot: &impl WriteBackend) -> Result<()> {
+        // the hot repository only contains tree packs, so all its files are cacheable
+        for (tpe, id) in &self.missing {
+            debug!("copying {tpe:?} {id:?} to hot repository");
+            let data = be.read_full(*tpe, id)?;
+            be_hot.write_bytes(*tpe, id, true, data)?;
+        }
+        for (tpe, id) in &self.orphaned {
+            debug!("removing {tpe:?} {id:?} from hot repository");
+            be_hot.remove(*tpe, id, true)?;
+        }
+        Ok(())
+    }
+}
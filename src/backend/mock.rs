@@ -0,0 +1,136 @@
+//! In-memory backend for tests
+
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use bytes::Bytes;
+
+use rustic_core::{FileType, Id, ReadBackend, RusticResult, WriteBackend};
+
+/// A backend keeping all files in memory, which can be told to fail
+#[derive(Debug, Default)]
+pub(crate) struct MemoryBackend {
+    /// The files by file type and id
+    files: Mutex<BTreeMap<(&'static str, Id), Bytes>>,
+    /// Number of operations which still fail before operations succeed again
+    failures: AtomicUsize,
+    /// Number of operations called so far
+    calls: AtomicUsize,
+}
+
+impl MemoryBackend {
+    /// Let the next `n` operations fail
+    pub(crate) fn fail_next(&self, n: usize) {
+        self.failures.store(n, Ordering::SeqCst);
+    }
+
+    /// Get the number of operations called so far
+    pub(crate) fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Count an operation and check if it fails
+    fn call(&self) -> RusticResult<()> {
+        _ = self.calls.fetch_add(1, Ordering::SeqCst);
+        if self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+        {
+            return Err(io::Error::other("simulated failure").into());
+        }
+        Ok(())
+    }
+
+    /// Get a file
+    fn get(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(&(tpe.dirname(), *id))
+            .cloned()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound).into())
+    }
+}
+
+impl ReadBackend for MemoryBackend {
+    fn location(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.call()?;
+        Ok(self
+            .files
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((dir, _), _)| *dir == tpe.dirname())
+            .map(|((_, id), data)| (*id, u32::try_from(data.len()).unwrap()))
+            .collect())
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        Ok(self
+            .list_with_size(tpe)?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.call()?;
+        self.get(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.call()?;
+        let (offset, length) = (offset as usize, length as usize);
+        Ok(self.get(tpe, id)?.slice(offset..offset + length))
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        false
+    }
+
+    fn warm_up(&self, _tpe: FileType, _id: &Id) -> RusticResult<()> {
+        Ok(())
+    }
+}
+
+impl WriteBackend for MemoryBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.call()
+    }
+
+    fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        buf: Bytes,
+    ) -> RusticResult<()> {
+        self.call()?;
+        _ = self.files.lock().unwrap().insert((tpe.dirname(), *id), buf);
+        Ok(())
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
+        self.call()?;
+        _ = self.files.lock().unwrap().remove(&(tpe.dirname(), *id));
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+//! Backend wrapper refusing all modifications
+
+use std::{fmt, io, sync::Arc};
+
+use bytes::Bytes;
+
+use rustic_core::{
+    FileType, Id, ReadBackend, RepositoryBackends, RusticError, RusticResult, WriteBackend,
+};
+
+/// Wrap the repository backends such that all modifications fail
+///
+/// # Arguments
+///
+/// * `backends` - The repository backends
+pub(crate) fn read_only(backends: RepositoryBackends) -> RepositoryBackends {
+    let wrap =
+        |be: Arc<dyn WriteBackend>| -> Arc<dyn WriteBackend> { Arc::new(ReadOnlyBackend(be)) };
+    RepositoryBackends::new(wrap(backends.repository()), backends.repo_hot().map(wrap))
+}
+
+/// A backend which only allows reading from the wrapped backend
+pub(crate) struct ReadOnlyBackend(Arc<dyn WriteBackend>);
+
+impl fmt::Debug for ReadOnlyBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReadOnlyBackend")
+            .field(&self.0.location())
+            .finish()
+    }
+}
+
+impl ReadOnlyBackend {
+    /// The error returned for all modifications
+    fn error(&self, action: &str) -> RusticError {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "cannot {action} in {}: repository is read-only",
+                self.0.location()
+            ),
+        )
+        .into()
+    }
+}
+
+impl ReadBackend for ReadOnlyBackend {
+    fn location(&self) -> String {
+        self.0.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.0.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.0.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.0.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.0.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.0.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.0.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for ReadOnlyBackend {
+    fn create(&self) -> RusticResult<()> {
+        Err(self.error("create repository"))
+    }
+
+    fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        _buf: Bytes,
+    ) -> RusticResult<()> {
+        Err(self.error(&format!("write {tpe:?} {id}")))
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
+        Err(self.error(&format!("remove {tpe:?} {id}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::mock::MemoryBackend;
+
+    /// Get an id for tests
+    fn id(n: u8) -> Id {
+        Id::from_hex(&format!("{n:064x}")).unwrap()
+    }
+
+    #[test]
+    fn modifications_are_refused() {
+        let mock = Arc::new(MemoryBackend::default());
+        mock.write_bytes(FileType::Snapshot, &id(1), false, Bytes::from("1"))
+            .unwrap();
+        let be = read_only(RepositoryBackends::new(mock.clone(), None)).repository();
+
+        let errors = [
+            be.create().unwrap_err(),
+            be.write_bytes(FileType::Snapshot, &id(2), false, Bytes::from("2"))
+                .unwrap_err(),
+            be.remove(FileType::Snapshot, &id(1), false).unwrap_err(),
+        ];
+        for err in errors {
+            assert!(err.to_string().contains("read-only"), "{err}");
+        }
+        assert_eq!(mock.list(FileType::Snapshot).unwrap(), vec![id(1)]);
+    }
+
+    #[test]
+    fn reads_pass_through() {
+        let mock = Arc::new(MemoryBackend::default());
+        mock.write_bytes(FileType::Index, &id(1), false, Bytes::from("index"))
+            .unwrap();
+        let be = read_only(RepositoryBackends::new(mock, None)).repository();
+
+        assert_eq!(be.list(FileType::Index).unwrap(), vec![id(1)]);
+        assert_eq!(
+            be.list_with_size(FileType::Index).unwrap(),
+            vec![(id(1), 5)]
+        );
+        assert_eq!(
+            be.read_full(FileType::Index, &id(1)).unwrap(),
+            Bytes::from("index")
+        );
+        assert_eq!(
+            be.read_partial(FileType::Index, &id(1), false, 1, 3)
+                .unwrap(),
+            Bytes::from("nde")
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Backend wrapper retrying failed operations
+//!
+//! Failed operations are retried with exponential backoff and jitter as configured by
+//! [`RetryOptions`]. As backend errors don't tell whether an error is permanent, all errors are
+//! retried.
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::sleep,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use log::warn;
+
+use rustic_core::{FileType, Id, ReadBackend, RepositoryBackends, RusticResult, WriteBackend};
+
+use crate::config::retry_options::{Operation, RetryOptions};
+
+/// Wrap the repository backends such that failed operations are retried
+///
+/// If retries are not enabled, the backends are returned unchanged.
+///
+/// # Arguments
+///
+/// * `backends` - The repository backends
+/// * `opts` - The retry options
+pub(crate) fn with_retries(backends: RepositoryBackends, opts: RetryOptions) -> RepositoryBackends {
+    if !opts.is_enabled() {
+        return backends;
+    }
+    // the retry budget is shared between all backends
+    let budget = opts
+        .retry_budget
+        .map(|budget| Arc::new(AtomicUsize::new(budget)));
+    let wrap = |be: Arc<dyn WriteBackend>| -> Arc<dyn WriteBackend> {
+        Arc::new(RetryBackend {
+            be,
+            opts,
+            budget: budget.clone(),
+        })
+    };
+    RepositoryBackends::new(wrap(backends.repository()), backends.repo_hot().map(wrap))
+}
+
+/// A backend which retries failed operations of the wrapped backend
+pub(crate) struct RetryBackend {
+    /// The wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// The retry options
+    opts: RetryOptions,
+    /// The number of retries which are still allowed
+    budget: Option<Arc<AtomicUsize>>,
+}
+
+impl fmt::Debug for RetryBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryBackend")
+            .field("be", &self.be.location())
+            .field("opts", &self.opts)
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+impl RetryBackend {
+    /// Try to take one retry from the budget
+    fn take_budget(&self) -> bool {
+        self.budget.as_ref().map_or(true, |budget| {
+            budget
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |b| b.checked_sub(1))
+                .is_ok()
+        })
+    }
+
+    /// Run `f` and retry it as configured for `op` if it fails
+    fn retry<T>(&self, op: Operation, mut f: impl FnMut() -> RusticResult<T>) -> RusticResult<T> {
+        let retries = self.opts.retries_for(op);
+        let mut retry = 0;
+        loop {
+            match f() {
+                Ok(result) => return Ok(result),
+                Err(err) if retry < retries && self.take_budget() => {
+                    // use a random wait time between half and the full backoff time
+                    let wait = self.opts.wait(retry);
+                    let jitter = RandomState::new().build_hasher().finish() % 1000;
+                    let wait = wait / 2 + wait.mul_f64(jitter as f64 / 2000.0);
+                    warn!(
+                        "{op:?} operation failed: {err}, retrying in {:.1}s...",
+                        wait.as_secs_f64()
+                    );
+                    sleep(wait);
+                    retry += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl ReadBackend for RetryBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.retry(Operation::List, || self.be.list_with_size(tpe))
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.retry(Operation::List, || self.be.list(tpe))
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.retry(Operation::Read, || self.be.read_full(tpe, id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.retry(Operation::Read, || {
+            self.be.read_partial(tpe, id, cacheable, offset, length)
+        })
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for RetryBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        self.retry(Operation::Write, || {
+            self.be.write_bytes(tpe, id, cacheable, buf.clone())
+        })
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.retry(Operation::Remove, || self.be.remove(tpe, id, cacheable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::mock::MemoryBackend;
+
+    /// Get retry options with the given number of retries and a short wait time
+    fn opts(retries: usize, budget: Option<usize>) -> RetryOptions {
+        RetryOptions {
+            retries: Some(retries),
+            retry_wait: Some(Duration::from_millis(1).into()),
+            retry_budget: budget,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn failed_operations_are_retried() {
+        let mock = Arc::new(MemoryBackend::default());
+        let backends = with_retries(RepositoryBackends::new(mock.clone(), None), opts(2, None));
+        let be = backends.repository();
+
+        mock.fail_next(2);
+        assert!(be.list(FileType::Snapshot).is_ok());
+        assert_eq!(mock.calls(), 3);
+    }
+
+    #[test]
+    fn operations_fail_after_all_retries() {
+        let mock = Arc::new(MemoryBackend::default());
+        let backends = with_retries(RepositoryBackends::new(mock.clone(), None), opts(1, None));
+        let be = backends.repository();
+
+        mock.fail_next(2);
+        assert!(be.list(FileType::Snapshot).is_err());
+        assert_eq!(mock.calls(), 2);
+    }
+
+    #[test]
+    fn budget_is_shared_between_backends() {
+        let mock = Arc::new(MemoryBackend::default());
+        let mock_hot = Arc::new(MemoryBackend::default());
+        let backends = with_retries(
+            RepositoryBackends::new(mock.clone(), Some(mock_hot.clone())),
+            opts(5, Some(1)),
+        );
+
+        mock.fail_next(1);
+        assert!(backends.repository().list(FileType::Index).is_ok());
+        assert_eq!(mock.calls(), 2);
+
+        // the budget is used up, so the hot backend is not retried
+        mock_hot.fail_next(1);
+        assert!(backends.repo_hot().unwrap().list(FileType::Index).is_err());
+        assert_eq!(mock_hot.calls(), 1);
+    }
+
+    #[test]
+    fn backends_are_unchanged_without_retries() {
+        let mock = Arc::new(MemoryBackend::default());
+        let backends = with_retries(RepositoryBackends::new(mock.clone(), None), opts(0, None));
+
+        mock.fail_next(1);
+        assert!(backends.repository().list(FileType::Index).is_err());
+        assert_eq!(mock.calls(), 1);
+    }
+}
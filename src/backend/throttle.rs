@@ -0,0 +1,179 @@
+//! Backend wrapper limiting the read bandwidth
+
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+
+use rustic_core::{FileType, Id, ReadBackend, RepositoryBackends, RusticResult, WriteBackend};
+
+/// Wrap the repository backends such that reading is throttled
+///
+/// # Arguments
+///
+/// * `backends` - The repository backends
+/// * `limit` - The maximum number of bytes to read per second, 0 means no limit
+/// * `pause` - The time to wait before starting to read from another pack
+pub(crate) fn throttled(
+    backends: RepositoryBackends,
+    limit: Option<u64>,
+    pause: Option<Duration>,
+) -> RepositoryBackends {
+    let limit = limit.filter(|limit| *limit > 0);
+    if limit.is_none() && pause.is_none() {
+        return backends;
+    }
+    // the bandwidth is shared between all backends
+    let state = Arc::new(Mutex::new(ThrottleState {
+        start: Instant::now(),
+        bytes: 0,
+        packs: HashSet::new(),
+        next_pack: None,
+    }));
+    let wrap = |be: Arc<dyn WriteBackend>| -> Arc<dyn WriteBackend> {
+        Arc::new(ThrottleBackend {
+            be,
+            limit,
+            pause,
+            state: state.clone(),
+        })
+    };
+    RepositoryBackends::new(wrap(backends.repository()), backends.repo_hot().map(wrap))
+}
+
+/// The state of the throttling
+#[derive(Debug)]
+struct ThrottleState {
+    /// Start of the reading
+    start: Instant,
+    /// Bytes read since start
+    bytes: u64,
+    /// Packs which have been read from
+    packs: HashSet<Id>,
+    /// Time at which reading from the next pack may start
+    next_pack: Option<Instant>,
+}
+
+/// A backend which throttles reading from the wrapped backend
+pub(crate) struct ThrottleBackend {
+    /// The wrapped backend
+    be: Arc<dyn WriteBackend>,
+    /// The maximum number of bytes to read per second
+    limit: Option<u64>,
+    /// The time to wait before starting to read from another pack
+    pause: Option<Duration>,
+    /// The throttle state
+    state: Arc<Mutex<ThrottleState>>,
+}
+
+impl fmt::Debug for ThrottleBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThrottleBackend")
+            .field("be", &self.be.location())
+            .field("limit", &self.limit)
+            .field("pause", &self.pause)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ThrottleBackend {
+    /// Wait before reading from the given file
+    ///
+    /// Reading from a pack which has not been read from before starts `pause` after the previous
+    /// pack has been started, also when reading from several threads.
+    fn before_read(&self, tpe: FileType, id: &Id) {
+        let Some(pause) = self.pause else {
+            return;
+        };
+        if !matches!(tpe, FileType::Pack) {
+            return;
+        }
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            if !state.packs.insert(*id) {
+                return;
+            }
+            let now = Instant::now();
+            let start = state.next_pack.map_or(now, |next| next.max(now));
+            state.next_pack = Some(start + pause);
+            start - now
+        };
+        sleep(wait);
+    }
+
+    /// Wait after reading `len` bytes until the bandwidth limit is met
+    fn after_read(&self, len: usize) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            state.bytes += len as u64;
+            let expected = Duration::from_secs_f64(state.bytes as f64 / limit as f64);
+            expected.saturating_sub(state.start.elapsed())
+        };
+        sleep(wait);
+    }
+}
+
+impl ReadBackend for ThrottleBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.before_read(tpe, id);
+        let data = self.be.read_full(tpe, id)?;
+        self.after_read(data.len());
+        Ok(data)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.before_read(tpe, id);
+        let data = self.be.read_partial(tpe, id, cacheable, offset, length)?;
+        self.after_read(data.len());
+        Ok(data)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for ThrottleBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        self.be.write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+}
@@ -1,30 +1,43 @@
 //! Rustic Subcommands
 
+pub(crate) mod audit_crypto;
 pub(crate) mod backup;
+pub(crate) mod cache;
 pub(crate) mod cat;
 pub(crate) mod check;
 pub(crate) mod completions;
 pub(crate) mod config;
 pub(crate) mod copy;
 pub(crate) mod diff;
+pub(crate) mod doctor;
+pub(crate) mod du;
 pub(crate) mod dump;
 pub(crate) mod find;
 pub(crate) mod forget;
+pub(crate) mod has_blob;
+pub(crate) mod index_paths;
 pub(crate) mod init;
 pub(crate) mod key;
 pub(crate) mod list;
 pub(crate) mod ls;
 pub(crate) mod merge;
+#[cfg(feature = "mount")]
+pub(crate) mod mount;
+pub(crate) mod prove;
 pub(crate) mod prune;
 pub(crate) mod repair;
 pub(crate) mod repoinfo;
 pub(crate) mod restore;
 pub(crate) mod self_update;
+#[cfg(feature = "serve")]
+pub(crate) mod serve;
 pub(crate) mod show_config;
 pub(crate) mod snapshots;
+pub(crate) mod stats;
 pub(crate) mod tag;
 #[cfg(feature = "tui")]
 pub(crate) mod tui;
+pub(crate) mod watch;
 #[cfg(feature = "webdav")]
 pub(crate) mod webdav;
 
@@ -33,18 +46,49 @@ use std::fs::File;
 use std::path::PathBuf;
 use std::str::FromStr;
 
+#[cfg(feature = "mount")]
+use crate::commands::mount::MountCmd;
+#[cfg(feature = "serve")]
+use crate::commands::serve::ServeCmd;
 #[cfg(feature = "webdav")]
 use crate::commands::webdav::WebDavCmd;
 use crate::{
+    backend::cache::remove_stale_cache_files,
     commands::{
-        backup::BackupCmd, cat::CatCmd, check::CheckCmd, completions::CompletionsCmd,
-        config::ConfigCmd, copy::CopyCmd, diff::DiffCmd, dump::DumpCmd, forget::ForgetCmd,
-        init::InitCmd, key::KeyCmd, list::ListCmd, ls::LsCmd, merge::MergeCmd, prune::PruneCmd,
-        repair::RepairCmd, repoinfo::RepoInfoCmd, restore::RestoreCmd, self_update::SelfUpdateCmd,
-        show_config::ShowConfigCmd, snapshots::SnapshotCmd, tag::TagCmd,
+        audit_crypto::AuditCryptoCmd,
+        backup::BackupCmd,
+        cache::CacheCmd,
+        cat::CatCmd,
+        check::CheckCmd,
+        completions::CompletionsCmd,
+        config::ConfigCmd,
+        copy::CopyCmd,
+        diff::DiffCmd,
+        doctor::DoctorCmd,
+        du::DuCmd,
+        dump::DumpCmd,
+        forget::ForgetCmd,
+        has_blob::HasBlobCmd,
+        index_paths::IndexPathsCmd,
+        init::InitCmd,
+        key::KeyCmd,
+        list::ListCmd,
+        ls::LsCmd,
+        merge::MergeCmd,
+        prove::{ProveCmd, VerifyProofCmd},
+        prune::PruneCmd,
+        repair::RepairCmd,
+        repoinfo::RepoInfoCmd,
+        restore::RestoreCmd,
+        self_update::SelfUpdateCmd,
+        show_config::ShowConfigCmd,
+        snapshots::SnapshotCmd,
+        stats::StatsCmd,
+        tag::TagCmd,
+        watch::WatchCmd,
     },
     config::{progress_options::ProgressOptions, AllRepositoryOptions, RusticConfig},
-    {Application, RUSTIC_APP},
+    status_err, systemd, Application, RUSTIC_APP,
 };
 
 use abscissa_core::{
@@ -60,7 +104,7 @@ use convert_case::{Case, Casing};
 use dialoguer::Password;
 use human_panic::setup_panic;
 use log::{log, warn, Level};
-use rustic_core::{IndexedFull, OpenStatus, ProgressBars, Repository};
+use rustic_core::{IndexedFull, OpenStatus, ProgressBars, Repository, RepositoryBackends};
 use simplelog::{CombinedLogger, LevelFilter, TermLogger, TerminalMode, WriteLogger};
 
 use self::find::FindCmd;
@@ -73,9 +117,15 @@ pub(super) mod constants {
 /// Subcommands need to be listed in an enum.
 #[derive(clap::Parser, Command, Debug, Runnable)]
 enum RusticCmd {
+    /// Check that sampled repository files are properly encrypted and authenticated
+    AuditCrypto(AuditCryptoCmd),
+
     /// Backup to the repository
     Backup(BackupCmd),
 
+    /// Show or clear the cache
+    Cache(CacheCmd),
+
     /// Show raw data of repository files and blobs
     Cat(CatCmd),
 
@@ -95,6 +145,12 @@ enum RusticCmd {
     /// Note that the exclude options only apply for comparison with a local path
     Diff(DiffCmd),
 
+    /// Check the configuration, backends, keys, cache and clock and report problems
+    Doctor(DoctorCmd),
+
+    /// Show the (deduplicated) disk usage of a snapshot/path
+    Du(DuCmd),
+
     /// dump the contents of a file in a snapshot to stdout
     Dump(DumpCmd),
 
@@ -104,6 +160,12 @@ enum RusticCmd {
     /// Remove snapshots from the repository
     Forget(ForgetCmd),
 
+    /// Check if blobs are contained in the repository index
+    HasBlob(HasBlobCmd),
+
+    /// Build the path index used by `find --from-index`
+    IndexPaths(IndexPathsCmd),
+
     /// Initialize a new repository
     Init(InitCmd),
 
@@ -119,6 +181,10 @@ enum RusticCmd {
     /// Merge snapshots
     Merge(MergeCmd),
 
+    /// Mount the repository as read-only filesystem
+    #[cfg(feature = "mount")]
+    Mount(MountCmd),
+
     /// Show a detailed overview of the snapshots within the repository
     Snapshots(SnapshotCmd),
 
@@ -129,6 +195,9 @@ enum RusticCmd {
     #[cfg_attr(not(feature = "self-update"), clap(hide = true))]
     SelfUpdate(SelfUpdateCmd),
 
+    /// Export a proof that a path is contained in a snapshot
+    Prove(ProveCmd),
+
     /// Remove unused data or repack repository pack files
     Prune(PruneCmd),
 
@@ -141,9 +210,22 @@ enum RusticCmd {
     /// Show general information about the repository
     Repoinfo(RepoInfoCmd),
 
+    /// Show statistics about the repository or selected snapshots
+    Stats(StatsCmd),
+
+    /// Serve the repository using the REST protocol
+    #[cfg(feature = "serve")]
+    Serve(ServeCmd),
+
     /// Change tags of snapshots
     Tag(TagCmd),
 
+    /// Verify a proof generated by `prove` against a snapshot id
+    VerifyProof(VerifyProofCmd),
+
+    /// Watch the backup sources and run a backup after changes
+    Watch(WatchCmd),
+
     /// Start a webdav server which allows to access the repository
     #[cfg(feature = "webdav")]
     Webdav(WebDavCmd),
@@ -173,6 +255,13 @@ impl Runnable for EntryPoint {
         // Set up panic hook for better error messages and logs
         setup_panic!();
 
+        if RUSTIC_APP.config().global.check_config {
+            if let Err(err) = RUSTIC_APP.config().validate() {
+                status_err!("{}", err);
+                RUSTIC_APP.shutdown(Shutdown::Crash);
+            }
+        }
+
         self.commands.run();
         RUSTIC_APP.shutdown(Shutdown::Graceful)
     }
@@ -216,11 +305,23 @@ impl Configurable<RusticConfig> for EntryPoint {
         let mut merge_logs = Vec::new();
 
         // get global options from command line / env and config file
-        if config.global.use_profiles.is_empty() {
-            config.merge_profile("rustic", &mut merge_logs, Level::Info)?;
+        // `config validate` reports invalid config files itself, so don't fail here
+        let validate = matches!(&self.commands, RusticCmd::Config(cmd) if cmd.is_validate());
+        let profiles = if config.global.use_profiles.is_empty() {
+            vec![("rustic".to_string(), Level::Info)]
         } else {
-            for profile in &config.global.use_profiles.clone() {
-                config.merge_profile(profile, &mut merge_logs, Level::Warn)?;
+            config
+                .global
+                .use_profiles
+                .iter()
+                .map(|profile| (profile.clone(), Level::Warn))
+                .collect()
+        };
+        for (profile, level_missing) in profiles {
+            if let Err(err) = config.merge_profile(&profile, &mut merge_logs, level_missing) {
+                if !validate {
+                    return Err(err);
+                }
             }
         }
 
@@ -233,14 +334,20 @@ impl Configurable<RusticConfig> for EntryPoint {
         let term_config = simplelog::ConfigBuilder::new()
             .set_time_level(LevelFilter::Off)
             .build();
+        // log to the journal instead of stderr if stderr is connected to it
+        let term_logger = |level: LevelFilter| {
+            systemd::journal_logger(level).unwrap_or_else(|| {
+                TermLogger::new(
+                    level,
+                    term_config.clone(),
+                    TerminalMode::Stderr,
+                    ColorChoice::Auto,
+                )
+            })
+        };
         match &config.global.log_file {
-            None => TermLogger::init(
-                level_filter,
-                term_config,
-                TerminalMode::Stderr,
-                ColorChoice::Auto,
-            )
-            .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?,
+            None => CombinedLogger::init(vec![term_logger(level_filter)])
+                .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?,
 
             Some(file) => {
                 let file_config = simplelog::ConfigBuilder::new()
@@ -256,14 +363,8 @@ impl Configurable<RusticConfig> for EntryPoint {
                         }
                         .context(e)
                     })?;
-                let term_logger = TermLogger::new(
-                    level_filter.min(LevelFilter::Warn),
-                    term_config,
-                    TerminalMode::Stderr,
-                    ColorChoice::Auto,
-                );
                 CombinedLogger::init(vec![
-                    term_logger,
+                    term_logger(level_filter.min(LevelFilter::Warn)),
                     WriteLogger::new(level_filter, file_config, file),
                 ])
                 .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
@@ -296,8 +397,23 @@ fn get_repository_with_progress<P>(
     repo_opts: &AllRepositoryOptions,
     po: P,
 ) -> Result<Repository<P, ()>> {
-    let backends = repo_opts.be.to_backends()?;
-    let repo = Repository::new_with_progress(&repo_opts.repo, &backends, po)?;
+    let backends = repo_opts.to_backends()?;
+    get_repository_with_backends(repo_opts, &backends, po)
+}
+
+/// Get the repository with the given options using already created backends
+///
+/// # Arguments
+///
+/// * `repo_opts` - The repository options
+/// * `backends` - The backends to use
+///
+fn get_repository_with_backends<P>(
+    repo_opts: &AllRepositoryOptions,
+    backends: &RepositoryBackends,
+    po: P,
+) -> Result<Repository<P, ()>> {
+    let repo = Repository::new_with_progress(&repo_opts.repo, backends, po)?;
     Ok(repo)
 }
 
@@ -335,10 +451,44 @@ fn open_repository_with_progress<P: Clone>(
     repo_opts: &AllRepositoryOptions,
     po: P,
 ) -> Result<Repository<P, OpenStatus>> {
+    let backends = repo_opts.to_backends()?;
+    let repo = open_with_password(get_repository_with_backends(repo_opts, &backends, po)?)?;
+    check_cache(repo_opts, &backends, &repo);
+    Ok(repo)
+}
+
+/// Remove files from the cache of an opened repository which don't match the repository
+///
+/// Errors are only logged, as the cache is not needed to work with the repository.
+///
+/// # Arguments
+///
+/// * `repo_opts` - The repository options
+/// * `backends` - The backends of the repository
+/// * `repo` - The opened repository
+fn check_cache<P>(
+    repo_opts: &AllRepositoryOptions,
+    backends: &RepositoryBackends,
+    repo: &Repository<P, OpenStatus>,
+) {
+    let Some(cache_dir) = repo_opts.cache_dir() else {
+        return;
+    };
+    let cache_dir = cache_dir.join(repo.config().id.to_hex().to_string());
+    if let Err(err) = remove_stale_cache_files(backends, &cache_dir) {
+        warn!("error checking the cache: {err}");
+    }
+}
+
+/// Open the given repository, asking for the password if it is not given
+///
+/// # Arguments
+///
+/// * `repo` - The repository to open
+fn open_with_password<P: Clone>(repo: Repository<P, ()>) -> Result<Repository<P, OpenStatus>> {
     if RUSTIC_APP.config().global.check_index {
         warn!("Option check-index is not supported and will be ignored!");
     }
-    let repo = get_repository_with_progress(repo_opts, po)?;
     match repo.password()? {
         // if password is given, directly return the result of find_key_in_backend and don't retry
         Some(pass) => {
@@ -367,6 +517,23 @@ fn open_repository(
     let po = RUSTIC_APP.config().global.progress_options;
     open_repository_with_progress(repo_opts, po)
 }
+
+/// Open the repository with the given options using already created backends
+///
+/// # Arguments
+///
+/// * `repo_opts` - The repository options
+/// * `backends` - The backends to use
+fn open_repository_with_backends(
+    repo_opts: &AllRepositoryOptions,
+    backends: &RepositoryBackends,
+) -> Result<Repository<ProgressOptions, OpenStatus>> {
+    let po = RUSTIC_APP.config().global.progress_options;
+    let repo = open_with_password(get_repository_with_backends(repo_opts, backends, po)?)?;
+    check_cache(repo_opts, backends, &repo);
+    Ok(repo)
+}
+
 /// helper function to get an opened and inedexed repo
 fn open_repository_indexed_with_progress<P: Clone + ProgressBars>(
     repo_opts: &AllRepositoryOptions,
@@ -0,0 +1,133 @@
+//! `audit-crypto` subcommand
+
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    hash::BuildHasher,
+};
+
+use crate::{commands::open_repository_with_backends, status_err, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use log::{error, info};
+use sha2::{Digest, Sha256};
+
+use rustic_core::{repofile::IndexFile, FileType, Id, ReadBackend};
+
+/// `audit-crypto` subcommand
+///
+/// Check that sampled repository files are well-formed ciphertext with valid MACs
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct AuditCryptoCmd {
+    /// Number of random files of each type to check
+    #[clap(long, value_name = "N", default_value = "10")]
+    sample: usize,
+}
+
+impl Runnable for AuditCryptoCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Choose up to `n` random ids
+fn sample(mut ids: Vec<Id>, n: usize) -> Vec<Id> {
+    let state = RandomState::new();
+    ids.sort_by_cached_key(|id| state.hash_one(id));
+    ids.truncate(n);
+    ids
+}
+
+/// Check if data looks like unencrypted JSON
+fn is_plaintext(data: &[u8]) -> bool {
+    serde_json::from_slice::<serde_json::Value>(data).is_ok()
+}
+
+impl AuditCryptoCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let backends = config.repository.to_backends()?;
+        let be = backends.repository();
+        let repo = open_repository_with_backends(&config.repository, &backends)?;
+
+        // key files are stored as JSON by design, the master key within is encrypted
+        let keys = be.list(FileType::Key)?;
+        info!("{} key(s) in use:", keys.len());
+        for key in &keys {
+            info!("  {key}");
+        }
+
+        let mut errors = 0;
+        let mut packs = Vec::new();
+        for tpe in [FileType::Snapshot, FileType::Index, FileType::Pack] {
+            let ids = sample(be.list(tpe)?, self.sample);
+            info!("checking {} {tpe:?} file(s)...", ids.len());
+            for id in ids {
+                let data = be.read_full(tpe, &id)?;
+                if format!("{:x}", Sha256::digest(&data)) != id.to_hex().to_string() {
+                    error!("{tpe:?} file {id}: contents don't match id");
+                    errors += 1;
+                }
+                if is_plaintext(&data) {
+                    error!("{tpe:?} file {id}: contains unencrypted JSON");
+                    errors += 1;
+                    continue;
+                }
+                if matches!(tpe, FileType::Pack) {
+                    // the pack ends with the length of the encrypted header
+                    let header_len = data
+                        .len()
+                        .checked_sub(4)
+                        .map(|pos| u32::from_le_bytes(data[pos..].try_into().unwrap()));
+                    if header_len.map_or(true, |len| len as usize + 4 > data.len()) {
+                        error!("{tpe:?} file {id}: invalid header length");
+                        errors += 1;
+                    }
+                    packs.push(id);
+                } else if let Err(err) = repo.cat_file(tpe, &id.to_hex().to_string()) {
+                    // decrypting verifies the MAC
+                    error!("{tpe:?} file {id}: {err}");
+                    errors += 1;
+                }
+            }
+        }
+
+        // verify the MACs of all blobs within the sampled packs
+        let mut blobs = BTreeMap::new();
+        for item in repo.stream_files::<IndexFile>()? {
+            let (_, index) = item?;
+            for pack in index.packs {
+                let pack_id: Id = pack.id.into();
+                if packs.contains(&pack_id) {
+                    let tpe = pack.blob_type();
+                    blobs.extend(pack.blobs.into_iter().map(|blob| (blob.id, (tpe, pack_id))));
+                }
+            }
+        }
+        info!("checking {} blob(s) of sampled packs...", blobs.len());
+        let repo = repo.to_indexed()?;
+        for (id, (tpe, pack_id)) in blobs {
+            let id = id.to_hex().to_string();
+            match repo.cat_blob(tpe, &id) {
+                Ok(data) if format!("{:x}", Sha256::digest(&data)) == id => {}
+                Ok(_) => {
+                    error!("{tpe:?} blob {id} in pack {pack_id}: contents don't match id");
+                    errors += 1;
+                }
+                Err(err) => {
+                    error!("{tpe:?} blob {id} in pack {pack_id}: {err}");
+                    errors += 1;
+                }
+            }
+        }
+
+        if errors > 0 {
+            bail!("{errors} error(s) found.");
+        }
+        info!("all sampled files are encrypted and authenticated.");
+        Ok(())
+    }
+}
@@ -1,13 +1,30 @@
 //! `backup` subcommand
 
-use std::path::PathBuf;
+mod excluded;
+
+use std::{
+    collections::BTreeSet,
+    io::Read,
+    ops::Bound,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    time::Instant,
+};
 
 use crate::{
-    commands::{get_repository, init::init, open_repository, snapshots::fill_table},
-    helpers::{bold_cell, bytes_size_to_string, table},
+    commands::{
+        forget::{check_retain_class, RETAIN_TAG_PREFIX},
+        get_repository,
+        init::init,
+        open_repository,
+        snapshots::fill_table,
+    },
+    helpers::{bold_cell, bytes_size_to_string, log_resource_usage, table, ResourceUsage},
     status_err, Application, RUSTIC_APP,
 };
 
+use self::excluded::why_excluded;
+
 use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::{bail, Context, Result};
 use clap::ValueHint;
@@ -18,8 +35,9 @@ use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
 use rustic_core::{
-    BackupOptions, CommandInput, ConfigOptions, KeyOptions, LocalSourceFilterOptions,
-    LocalSourceSaveOptions, ParentOptions, PathList, SnapshotOptions,
+    repofile::SnapshotFile, BackupOptions, CommandInput, ConfigOptions, KeyOptions,
+    LocalSourceFilterOptions, LocalSourceSaveOptions, ParentOptions, PathList, SnapshotOptions,
+    StringList,
 };
 
 /// `backup` subcommand
@@ -40,6 +58,22 @@ pub struct BackupCmd {
     #[serde(skip)]
     cli_sources: Vec<String>,
 
+    /// Read the paths to backup from the given file, one path per line (can be specified multiple
+    /// times), use - for stdin. Empty lines and lines starting with # are ignored. The listed paths
+    /// are backed up in addition to the given SOURCEs. Listed directories are backed up
+    /// recursively, unless some of their contents are listed, too. Can't be combined with
+    /// --glob, --iglob, --glob-file or --iglob-file.
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    #[merge(skip)]
+    #[serde(skip)]
+    files_from: Vec<PathBuf>,
+
+    /// Like --files-from, but paths are separated by NUL bytes and taken as they are
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    #[merge(skip)]
+    #[serde(skip)]
+    files_from_raw: Vec<PathBuf>,
+
     /// Set filename to be used when backing up from stdin
     #[clap(long, value_name = "FILENAME", default_value = "stdin", value_hint = ValueHint::FilePath)]
     #[merge(skip)]
@@ -53,6 +87,11 @@ pub struct BackupCmd {
     #[clap(long, value_name = "PATH", value_hint = ValueHint::DirPath)]
     as_path: Option<PathBuf>,
 
+    /// Retention class of the snapshot (e.g. daily, weekly, archive), saved as tag
+    /// "retain:CLASS". Use `forget --keep-retain` to keep snapshots by their retention class.
+    #[clap(long, value_name = "CLASS")]
+    retain: Option<String>,
+
     /// Ignore save options
     #[clap(flatten)]
     #[serde(flatten)]
@@ -63,7 +102,7 @@ pub struct BackupCmd {
     #[merge(strategy = merge::bool::overwrite_false)]
     pub no_scan: bool,
 
-    /// Output generated snapshot in json format
+    /// Output generated snapshot and the used resources in json format
     #[clap(long)]
     #[merge(strategy = merge::bool::overwrite_false)]
     json: bool,
@@ -83,6 +122,22 @@ pub struct BackupCmd {
     #[merge(strategy = merge::bool::overwrite_false)]
     init: bool,
 
+    /// Refuse to read glob files which are symlinks (by default, symlinks are only followed if
+    /// they are owned by root or by the owner of their directory and point to a file which only
+    /// root or the current user can modify). Note: This doesn't apply to ignore files within the
+    /// backup sources (like .gitignore or --custom-ignorefile), these are read while walking the
+    /// sources.
+    #[clap(long)]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    no_follow_config_symlinks: bool,
+
+    /// Don't backup, but explain if and why the given path is excluded by the exclude options (can
+    /// be specified multiple times)
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::AnyPath)]
+    #[merge(skip)]
+    #[serde(skip)]
+    why_excluded: Vec<PathBuf>,
+
     /// Parent processing options
     #[clap(flatten, next_help_heading = "Options for parent processing")]
     #[serde(flatten)]
@@ -134,48 +189,37 @@ pub(crate) fn merge_sources(left: &mut Vec<BackupCmd>, mut right: Vec<BackupCmd>
     left.dedup_by(|opt1, opt2| opt1.sources == opt2.sources);
 }
 
-impl Runnable for BackupCmd {
-    fn run(&self) {
-        if let Err(err) = self.inner_run() {
-            status_err!("{}", err);
-            RUSTIC_APP.shutdown(Shutdown::Crash);
-        };
-    }
-}
-
 impl BackupCmd {
-    fn inner_run(&self) -> Result<()> {
-        let config = RUSTIC_APP.config();
-        let repo = get_repository(&config.repository)?;
-        // Initialize repository if --init is set and it is not yet initialized
-        let repo = if self.init && repo.config_id()?.is_none() {
-            if config.global.dry_run {
-                bail!(
-                    "cannot initialize repository {} in dry-run mode!",
-                    repo.name
-                );
-            }
-            init(repo, &self.key_opts, &self.config_opts)?
-        } else {
-            open_repository(&config.repository)?
+    /// Check the `[backup]` section of the config file and sanitize the sources of all
+    /// `[[backup.snapshots]]` sections
+    ///
+    /// # Errors
+    ///
+    /// If the section contains keys which are not valid
+    ///
+    /// # Returns
+    ///
+    /// For each `[[backup.snapshots]]` section, the sanitized sources or the error sanitizing them
+    fn config_sources(&self) -> Result<Vec<Result<PathList>>> {
+        let mut problems = Vec::new();
+        // check for "source(s)" fields, check is not done by serde, see above.
+        if !self.sources.is_empty() {
+            problems.push("key \"source\" is not valid in the [backup] section!");
         }
-        .to_indexed_ids()?;
-
-        // manually check for a "source" field, check is not done by serde, see above.
-        if !config.backup.sources.is_empty() {
-            bail!("key \"source\" is not valid in the [backup] section!");
+        if self.snapshots.iter().any(|opt| !opt.snapshots.is_empty()) {
+            problems.push("key \"sources\" is not valid in a [[backup.sources]] section!");
         }
-
-        let snapshot_opts = &config.backup.snapshots;
-
-        // manually check for a "sources" field, check is not done by serde, see above.
-        if snapshot_opts.iter().any(|opt| !opt.snapshots.is_empty()) {
-            bail!("key \"sources\" is not valid in a [[backup.sources]] section!");
+        if !problems.is_empty() {
+            bail!("{}", problems.join("\n"));
         }
 
-        let config_snapshot_sources: Vec<_> = snapshot_opts
+        Ok(self
+            .snapshots
             .iter()
-            .map(|opt| -> Result<_> {
+            .map(|opt| {
+                if opt.sources.is_empty() {
+                    bail!("missing key \"sources\" in a [[backup.sources]] section!");
+                }
                 Ok(PathList::from_iter(&opt.sources)
                     .sanitize()
                     .with_context(|| {
@@ -186,57 +230,271 @@ impl BackupCmd {
                     })?
                     .merge())
             })
-            .filter_map(|p| match p {
-                Ok(paths) => Some(paths),
-                Err(err) => {
-                    warn!("{err}");
-                    None
-                }
-            })
+            .collect())
+    }
+
+    /// Check the backup config for problems which are not detected when parsing the config file
+    ///
+    /// # Returns
+    ///
+    /// The list of problems found
+    pub(crate) fn config_problems(&self) -> Vec<String> {
+        let sources = match self.config_sources() {
+            Ok(sources) => sources,
+            Err(err) => return vec![err.to_string()],
+        };
+        let mut problems = Vec::new();
+        for paths in sources {
+            match paths {
+                Ok(paths) => problems.extend(
+                    paths
+                        .paths()
+                        .into_iter()
+                        .filter(|path| std::fs::symlink_metadata(path).is_err())
+                        .map(|path| format!("backup source {path:?} is not accessible!")),
+                ),
+                Err(err) => problems.push(format!("{err:#}")),
+            }
+        }
+        problems
+    }
+}
+
+/// Output of `backup --json`: the saved snapshot together with the used resources
+#[derive(Serialize)]
+struct BackupJson<'a> {
+    /// The saved snapshot
+    #[serde(flatten)]
+    snapshot: &'a SnapshotFile,
+    /// The resources used so far
+    resources: ResourceUsage,
+}
+
+impl Runnable for BackupCmd {
+    fn run(&self) {
+        let start = Instant::now();
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+        log_resource_usage(start);
+    }
+}
+
+impl BackupCmd {
+    /// Read the paths given in the files of `--files-from` and `--files-from-raw`
+    fn read_files_from(&self) -> Result<Vec<PathBuf>> {
+        let read = |file: &PathBuf| -> Result<Vec<u8>> {
+            if file.as_os_str() == "-" {
+                let mut data = Vec::new();
+                _ = std::io::stdin().read_to_end(&mut data)?;
+                Ok(data)
+            } else {
+                std::fs::read(file).with_context(|| format!("error reading {file:?}"))
+            }
+        };
+
+        let mut paths = Vec::new();
+        for file in &self.files_from {
+            let data = String::from_utf8(read(file)?)
+                .with_context(|| format!("{file:?} is not valid UTF-8, use --files-from-raw"))?;
+            paths.extend(
+                data.lines()
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(PathBuf::from),
+            );
+        }
+        for file in &self.files_from_raw {
+            for path in read(file)?.split(|b| *b == 0).filter(|p| !p.is_empty()) {
+                #[cfg(unix)]
+                let path = {
+                    use std::os::unix::ffi::OsStrExt;
+                    PathBuf::from(std::ffi::OsStr::from_bytes(path))
+                };
+                #[cfg(not(unix))]
+                let path = PathBuf::from(std::str::from_utf8(path)?);
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Check if stdin is used, either as backup source or to read the paths to backup
+    pub(crate) fn uses_stdin(&self) -> bool {
+        self.cli_sources.iter().any(|source| source == "-")
+            || self
+                .files_from
+                .iter()
+                .chain(&self.files_from_raw)
+                .any(|file| file.as_os_str() == "-")
+    }
+
+    /// Get the source paths which are backed up, either given on the command line, given by
+    /// `--files-from` or in the config file
+    pub(crate) fn source_paths(&self) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .sources_with_options()?
+            .into_iter()
+            .flat_map(|(sources, _)| sources.paths())
+            .collect())
+    }
+
+    /// Get the backup sources together with their options merged from the config file
+    fn sources_with_options(&self) -> Result<Vec<(PathList, Self)>> {
+        let config = RUSTIC_APP.config();
+
+        let snapshot_opts = &config.backup.snapshots;
+        let config_snapshot_sources: Vec<_> = config
+            .backup
+            .config_sources()?
+            .into_iter()
+            .map(|paths| paths.inspect_err(|err| warn!("{err}")).ok())
             .collect();
 
-        let snapshot_sources = match (self.cli_sources.is_empty(), snapshot_opts.is_empty()) {
+        let mut cli_sources: Vec<_> = self.cli_sources.iter().map(PathBuf::from).collect();
+        if !self.files_from.is_empty() || !self.files_from_raw.is_empty() {
+            let filter = &self.ignore_filter_opts;
+            if !filter.globs.is_empty()
+                || !filter.iglobs.is_empty()
+                || !filter.glob_files.is_empty()
+                || !filter.iglob_files.is_empty()
+            {
+                bail!("--files-from can't be combined with --glob, --iglob, --glob-file or --iglob-file, only list the paths to backup instead.");
+            }
+            let paths = self
+                .read_files_from()?
+                .iter()
+                .map(|path| absolute_path(path))
+                .collect::<Result<BTreeSet<_>>>()?;
+            if paths.is_empty() {
+                bail!("no paths given in --files-from or --files-from-raw.");
+            }
+            cli_sources.extend(files_from_sources(&paths));
+        }
+
+        let snapshot_sources = match (cli_sources.is_empty(), snapshot_opts.is_empty()) {
             (false, _) => {
-                let item = PathList::from_iter(&self.cli_sources).sanitize()?;
+                let item = PathList::from_iter(&cli_sources).sanitize()?;
                 vec![item]
             }
             (true, false) => {
                 info!("using all backup sources from config file.");
-                config_snapshot_sources.clone()
+                config_snapshot_sources.iter().flatten().cloned().collect()
             }
             (true, true) => {
                 bail!("no backup source given.");
             }
         };
 
-        for sources in snapshot_sources {
-            let mut opts = self.clone();
+        snapshot_sources
+            .into_iter()
+            .map(|sources| {
+                let mut opts = self.clone();
+                // merge Options from config file, if given
+                if let Some(idx) = config_snapshot_sources
+                    .iter()
+                    .position(|s| s.as_ref() == Some(&sources))
+                {
+                    info!("merging source={sources} section from config file");
+                    opts.merge(snapshot_opts[idx].clone());
+                }
+                if let Some(path) = &opts.as_path {
+                    // as_path only works in combination with a single target
+                    if sources.len() > 1 {
+                        bail!("as-path only works with a single target!");
+                    }
+                    // merge Options from config file using as_path, if given
+                    if let Some(path) = path.as_os_str().to_str() {
+                        if let Some(idx) = snapshot_opts
+                            .iter()
+                            .position(|opt| opt.sources == vec![path])
+                        {
+                            info!("merging source=\"{path}\" section from config file");
+                            opts.merge(snapshot_opts[idx].clone());
+                        }
+                    }
+                }
+
+                // merge "backup" section from config file, if given
+                opts.merge(config.backup.clone());
 
-            // merge Options from config file, if given
-            if let Some(idx) = config_snapshot_sources.iter().position(|s| s == &sources) {
-                info!("merging source={sources} section from config file");
-                opts.merge(snapshot_opts[idx].clone());
-            }
-            if let Some(path) = &opts.as_path {
-                // as_path only works in combination with a single target
-                if sources.len() > 1 {
-                    bail!("as-path only works with a single target!");
+                // read glob files here, such that they are checked and read using the same file
+                let filter = &mut opts.ignore_filter_opts;
+                for file in std::mem::take(&mut filter.glob_files) {
+                    let globs = read_config_file(Path::new(&file), opts.no_follow_config_symlinks)?;
+                    filter.globs.extend(globs.lines().map(String::from));
+                }
+                for file in std::mem::take(&mut filter.iglob_files) {
+                    let globs = read_config_file(Path::new(&file), opts.no_follow_config_symlinks)?;
+                    filter.iglobs.extend(globs.lines().map(String::from));
+                }
+                Ok((sources, opts))
+            })
+            .collect()
+    }
+
+    /// Print for each path given by `--why-excluded` if and why it is excluded from the backup
+    fn explain_excluded(&self) -> Result<()> {
+        let sources = self.sources_with_options()?;
+        for path in &self.why_excluded {
+            // don't resolve the path itself, it may be an excluded symlink
+            let path = match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+                    parent.canonicalize()?.join(name)
                 }
-                // merge Options from config file using as_path, if given
-                if let Some(path) = path.as_os_str().to_str() {
-                    if let Some(idx) = snapshot_opts
-                        .iter()
-                        .position(|opt| opt.sources == vec![path])
+                (_, Some(name)) => std::env::current_dir()?.join(name),
+                _ => path.canonicalize()?,
+            };
+            let mut found = false;
+            for (sources, opts) in &sources {
+                for source in sources.paths() {
+                    let source = source.canonicalize().unwrap_or(source);
+                    if !path.starts_with(&source) {
+                        continue;
+                    }
+                    found = true;
+                    match why_excluded(&path, &source, &opts.ignore_filter_opts)
+                        .with_context(|| format!("error checking {path:?}"))?
                     {
-                        info!("merging source=\"{path}\" section from config file");
-                        opts.merge(snapshot_opts[idx].clone());
+                        Some(reason) => println!("{reason} (source {})", source.display()),
+                        None => println!(
+                            "{} is included in the backup of {}",
+                            path.display(),
+                            source.display()
+                        ),
                     }
                 }
             }
+            if !found {
+                println!("{} is not within any backup source", path.display());
+            }
+        }
+        Ok(())
+    }
 
-            // merge "backup" section from config file, if given
-            opts.merge(config.backup.clone());
+    pub(crate) fn inner_run(&self) -> Result<()> {
+        let start = Instant::now();
+        if !self.why_excluded.is_empty() {
+            return self.explain_excluded();
+        }
 
+        let config = RUSTIC_APP.config();
+        let repo = get_repository(&config.repository)?;
+        // Initialize repository if --init is set and it is not yet initialized
+        let repo = if self.init && repo.config_id()?.is_none() {
+            if config.global.dry_run {
+                bail!(
+                    "cannot initialize repository {} in dry-run mode!",
+                    repo.name
+                );
+            }
+            init(repo, &self.key_opts, &self.config_opts)?
+        } else {
+            open_repository(&config.repository)?
+        }
+        .to_indexed_ids()?;
+
+        for (sources, mut opts) in self.sources_with_options()? {
             let backup_opts = BackupOptions::default()
                 .stdin_filename(opts.stdin_filename)
                 .stdin_command(opts.stdin_command)
@@ -246,11 +504,20 @@ impl BackupCmd {
                 .ignore_filter_opts(opts.ignore_filter_opts)
                 .no_scan(opts.no_scan)
                 .dry_run(config.global.dry_run);
+            if let Some(class) = &opts.retain {
+                check_retain_class(class)?;
+                let tag = StringList::from_str(&format!("{RETAIN_TAG_PREFIX}{class}"))?;
+                opts.snap_opts.tags.push(tag);
+            }
             let snap = repo.backup(&backup_opts, &sources, opts.snap_opts.to_snapshot()?)?;
 
             if opts.json {
+                let output = BackupJson {
+                    snapshot: &snap,
+                    resources: ResourceUsage::since(start),
+                };
                 let mut stdout = std::io::stdout();
-                serde_json::to_writer_pretty(&mut stdout, &snap)?;
+                serde_json::to_writer_pretty(&mut stdout, &output)?;
             } else if opts.long {
                 let mut table = table();
 
@@ -292,3 +559,237 @@ impl BackupCmd {
         Ok(())
     }
 }
+
+/// Make the given path absolute without resolving symlinks
+fn absolute_path(path: &Path) -> Result<PathBuf> {
+    let mut absolute = if path.is_absolute() {
+        PathBuf::new()
+    } else {
+        std::env::current_dir()?
+    };
+    for comp in path.components() {
+        match comp {
+            Component::CurDir => {}
+            Component::ParentDir => _ = absolute.pop(),
+            comp => absolute.push(comp),
+        }
+    }
+    Ok(absolute)
+}
+
+/// Get the paths to backup from the paths listed by `--files-from`
+///
+/// A directory is only given as source if none of its contents are listed, otherwise all its
+/// contents would be backed up.
+///
+/// # Arguments
+///
+/// * `paths` - The listed absolute paths
+fn files_from_sources(paths: &BTreeSet<PathBuf>) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .filter(|path| {
+            // the contents of a directory directly follow it in the sorted set
+            !paths
+                .range::<PathBuf, _>((Bound::Excluded(*path), Bound::Unbounded))
+                .next()
+                .is_some_and(|next| next.starts_with(path))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Read a file containing backup configuration (like a glob file)
+///
+/// A symlink is refused if `no_follow` is set. Otherwise, on unix it is refused if it is not owned
+/// by root or the owner of its directory, if the file it points to is not owned by root or the
+/// current user or is writable by others, or if a directory containing that file is writable by
+/// others. This prevents other users from changing what is backed up.
+///
+/// The checks are done on the opened file, which is then read, so the file can't be exchanged
+/// between checking and reading it.
+///
+/// # Note
+///
+/// Ignore files within the backup sources (`.gitignore` or `--custom-ignorefile`) are read by the
+/// source walker of `rustic_core`, which follows symlinks without these checks.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file to read
+/// * `no_follow` - Whether to refuse all symlinks
+///
+/// # Errors
+///
+/// If the file can't be read or is a symlink which is not trusted
+fn read_config_file(path: &Path, no_follow: bool) -> Result<String> {
+    let meta = std::fs::symlink_metadata(path)
+        .with_context(|| format!("error reading metadata of {path:?}"))?;
+    let is_symlink = meta.file_type().is_symlink();
+    if is_symlink && no_follow {
+        bail!("refusing to follow symlink {path:?}, as no-follow-config-symlinks is set.");
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    _ = options.read(true);
+    #[cfg(unix)]
+    if !is_symlink {
+        // the file must not be replaced by a symlink after the check above
+        use std::os::unix::fs::OpenOptionsExt;
+        _ = options.custom_flags(libc::O_NOFOLLOW);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("error opening {path:?}"))?;
+    #[cfg(unix)]
+    if is_symlink {
+        check_symlink_target(path, &meta, &file)?;
+    }
+
+    let mut content = String::new();
+    _ = file
+        .read_to_string(&mut content)
+        .with_context(|| format!("error reading {path:?}"))?;
+    Ok(content)
+}
+
+/// Check that a symlinked file containing backup configuration can be trusted
+///
+/// # Arguments
+///
+/// * `path` - The path of the symlink
+/// * `link` - The metadata of the symlink itself
+/// * `file` - The opened file the symlink points to
+///
+/// # Errors
+///
+/// If the symlink or the file it points to is not trusted
+#[cfg(unix)]
+fn check_symlink_target(path: &Path, link: &std::fs::Metadata, file: &std::fs::File) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let euid = nix::unistd::geteuid().as_raw();
+    let trusted = |uid| uid == 0 || uid == euid;
+    // writable by group or others, unless the sticky bit prevents them from replacing files
+    let writable_by_others = |mode: u32| mode & 0o022 != 0 && mode & 0o1000 == 0;
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir_owner = std::fs::metadata(dir)
+        .with_context(|| format!("error reading metadata of {dir:?}"))?
+        .uid();
+    if link.uid() != 0 && link.uid() != dir_owner {
+        bail!(
+            "refusing to follow symlink {path:?}: it is owned by uid {} which neither is root nor owns {dir:?}.",
+            link.uid()
+        );
+    }
+
+    let target = file
+        .metadata()
+        .with_context(|| format!("error reading metadata of symlink target of {path:?}"))?;
+    if !trusted(target.uid()) {
+        bail!(
+            "refusing to follow symlink {path:?}: it points to a file owned by uid {} which neither is root nor the current user.",
+            target.uid()
+        );
+    }
+    if target.mode() & 0o022 != 0 {
+        bail!(
+            "refusing to follow symlink {path:?}: it points to a file which is writable by others."
+        );
+    }
+
+    let target_path = path
+        .canonicalize()
+        .with_context(|| format!("error resolving symlink {path:?}"))?;
+    let resolved = std::fs::metadata(&target_path)?;
+    if (resolved.dev(), resolved.ino()) != (target.dev(), target.ino()) {
+        bail!("refusing to follow symlink {path:?}: it has been changed while reading it.");
+    }
+    for dir in target_path.ancestors().skip(1) {
+        let meta =
+            std::fs::metadata(dir).with_context(|| format!("error reading metadata of {dir:?}"))?;
+        if !trusted(meta.uid()) || writable_by_others(meta.mode()) {
+            bail!("refusing to follow symlink {path:?}: {dir:?} can be modified by other users.");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::{lchown, symlink, PermissionsExt};
+
+    use rstest::rstest;
+    use tempfile::TempDir;
+
+    #[rstest]
+    #[case(&["/a/b", "/a/c"], &["/a/b", "/a/c"])]
+    #[case(&["/a", "/a/b", "/a/c/d"], &["/a/b", "/a/c/d"])]
+    #[case(&["/a", "/a b", "/a/b"], &["/a/b", "/a b"])]
+    #[case(&["/a", "/ab"], &["/a", "/ab"])]
+    fn files_from_sources_skips_directories_with_listed_contents(
+        #[case] paths: &[&str],
+        #[case] expected: &[&str],
+    ) {
+        let paths = paths.iter().map(PathBuf::from).collect();
+        let expected: Vec<_> = expected.iter().map(PathBuf::from).collect();
+        assert_eq!(files_from_sources(&paths), expected);
+    }
+
+    /// Create a glob file with the given permissions and a symlink to it
+    fn glob_file_link(mode: u32) -> Result<(TempDir, PathBuf)> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("globs");
+        std::fs::write(&file, "!*.log\n")?;
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(mode))?;
+        let link = dir.path().join("link");
+        symlink(&file, &link)?;
+        Ok((dir, link))
+    }
+
+    #[test]
+    fn read_config_file_follows_trusted_symlink() -> Result<()> {
+        let (_dir, link) = glob_file_link(0o644)?;
+        assert_eq!(read_config_file(&link, false)?, "!*.log\n");
+        Ok(())
+    }
+
+    #[test]
+    fn read_config_file_refuses_symlink_with_no_follow() -> Result<()> {
+        let (dir, link) = glob_file_link(0o644)?;
+        assert!(read_config_file(&link, true).is_err());
+        // the file itself can still be read
+        assert_eq!(
+            read_config_file(&dir.path().join("globs"), true)?,
+            "!*.log\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn read_config_file_refuses_world_writable_target() -> Result<()> {
+        let (_dir, link) = glob_file_link(0o666)?;
+        let err = read_config_file(&link, false).unwrap_err();
+        assert!(err.to_string().contains("writable by others"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn read_config_file_refuses_symlink_of_other_user() -> Result<()> {
+        // only root can create a symlink owned by another user
+        if !nix::unistd::geteuid().is_root() {
+            return Ok(());
+        }
+        let (_dir, link) = glob_file_link(0o644)?;
+        lchown(&link, Some(65534), None)?;
+        let err = read_config_file(&link, false).unwrap_err();
+        assert!(err.to_string().contains("owned by uid 65534"), "{err}");
+        Ok(())
+    }
+}
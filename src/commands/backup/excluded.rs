@@ -0,0 +1,268 @@
+//! Explain why a path is excluded from a backup
+//!
+//! The exclude options are evaluated the same way the backup source does: globs are applied as
+//! overrides, ignore files are read from the directory of a path and all its parents (deeper files
+//! take precedence) and excluded directories exclude everything below them.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+
+use rustic_core::LocalSourceFilterOptions;
+
+/// Get the reason why the given path is excluded from the backup of `source`
+///
+/// Returns `None` if the path is included.
+///
+/// # Arguments
+///
+/// * `path` - The path to check, must be within `source`
+/// * `source` - The backup source containing the path
+/// * `opts` - The exclude options used for the backup
+pub(super) fn why_excluded(
+    path: &Path,
+    source: &Path,
+    opts: &LocalSourceFilterOptions,
+) -> Result<Option<String>> {
+    // globs are used as overrides, i.e. with inverted gitignore semantics: a glob includes and
+    // a glob starting with "!" excludes
+    let mut builder = GitignoreBuilder::new("");
+    for glob in &opts.globs {
+        _ = builder.add_line(None, glob)?;
+    }
+    for file in &opts.glob_files {
+        for line in fs::read_to_string(file)?.lines() {
+            _ = builder.add_line(None, line)?;
+        }
+    }
+    _ = builder.case_insensitive(true)?;
+    for glob in &opts.iglobs {
+        _ = builder.add_line(None, glob)?;
+    }
+    for file in &opts.iglob_files {
+        for line in fs::read_to_string(file)?.lines() {
+            _ = builder.add_line(None, line)?;
+        }
+    }
+    let globs = builder.build()?;
+
+    let mut ignore_files = opts.custom_ignorefiles.clone();
+    if opts.git_ignore && (opts.no_require_git || in_git_repo(source)) {
+        ignore_files.push(".gitignore".to_string());
+    }
+
+    #[cfg(unix)]
+    let source_dev = {
+        use std::os::unix::fs::MetadataExt;
+        fs::metadata(source)?.dev()
+    };
+
+    // check the path and all its parents below the source, an excluded parent excludes the path
+    let mut parents: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|p| *p != source)
+        .map(Path::to_path_buf)
+        .collect();
+    parents.reverse();
+    for p in &parents {
+        let meta = fs::symlink_metadata(p)?;
+        let is_dir = meta.is_dir();
+        let name = p.display();
+
+        let included = match globs.matched(p, is_dir) {
+            Match::Whitelist(glob) => {
+                return Ok(Some(format!(
+                    "{name} is excluded by glob \"{}\"",
+                    glob.original()
+                )));
+            }
+            Match::None if !is_dir && globs.num_ignores() > 0 => {
+                return Ok(Some(format!("{name} is not matched by any include glob")));
+            }
+            Match::Ignore(_) => true,
+            Match::None => false,
+        };
+
+        // globs take precedence over ignore files
+        if !included {
+            if let Some(reason) = ignore_file_match(p, is_dir, &ignore_files) {
+                return Ok(Some(reason));
+            }
+        }
+
+        if is_dir {
+            if let Some(file) = opts
+                .exclude_if_present
+                .iter()
+                .find(|file| p.join(file).exists())
+            {
+                return Ok(Some(format!(
+                    "{name} is excluded by exclude-if-present, it contains \"{file}\""
+                )));
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if opts.one_file_system && meta.dev() != source_dev {
+                return Ok(Some(format!(
+                    "{name} is on another file system and one-file-system is set"
+                )));
+            }
+        }
+
+        if let Some(limit) = opts.exclude_larger_than {
+            if meta.is_file() && meta.len() > limit.as_u64() {
+                return Ok(Some(format!(
+                    "{name} is larger than exclude-larger-than ({limit})"
+                )));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Check if a path is within a git repository
+fn in_git_repo(path: &Path) -> bool {
+    path.ancestors().any(|p| p.join(".git").exists())
+}
+
+/// Find the ignore file rule which excludes the given path, if any
+///
+/// # Arguments
+///
+/// * `path` - The path to check
+/// * `is_dir` - Whether the path is a directory
+/// * `ignore_files` - Names of the ignore files to read
+fn ignore_file_match(path: &Path, is_dir: bool, ignore_files: &[String]) -> Option<String> {
+    for dir in path.ancestors().skip(1) {
+        for name in ignore_files {
+            let file = dir.join(name);
+            if !file.is_file() {
+                continue;
+            }
+            let (gitignore, _) = Gitignore::new(&file);
+            match gitignore.matched(path, is_dir) {
+                Match::Ignore(glob) => {
+                    return Some(format!(
+                        "{} is excluded by \"{}\" in {}",
+                        path.display(),
+                        glob.original(),
+                        file.display()
+                    ));
+                }
+                Match::Whitelist(_) => return None,
+                Match::None => {}
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bytesize::ByteSize;
+    use rstest::rstest;
+    use tempfile::TempDir;
+
+    /// Create a backup source with some files to check
+    fn source() -> Result<TempDir> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("a"))?;
+        fs::create_dir_all(dir.path().join("cache"))?;
+        fs::write(dir.path().join("a/keep.txt"), "keep")?;
+        fs::write(dir.path().join("a/skip.log"), "skip")?;
+        fs::write(dir.path().join("a/important.log"), "important")?;
+        fs::write(dir.path().join("cache/CACHEDIR.TAG"), "")?;
+        fs::write(dir.path().join("cache/data.txt"), "data")?;
+        fs::write(dir.path().join("big.txt"), [0; 200])?;
+        fs::write(dir.path().join("small.txt"), [0; 50])?;
+        fs::write(dir.path().join(".gitignore"), "*.log\n!important.log\n")?;
+        Ok(dir)
+    }
+
+    /// Check the reason why `path` is excluded from the source with the given options
+    fn check(opts: &LocalSourceFilterOptions, path: &str, expected: Option<&str>) -> Result<()> {
+        let dir = source()?;
+        let reason = why_excluded(&dir.path().join(path), dir.path(), opts)?;
+        match (reason, expected) {
+            (Some(reason), Some(expected)) => assert!(
+                reason.contains(expected),
+                "reason {reason:?} does not contain {expected:?}"
+            ),
+            (reason, expected) => assert_eq!(reason, expected.map(String::from)),
+        }
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(&["!*.log"], "a/skip.log", Some("excluded by glob \"!*.log\""))]
+    #[case(&["!*.log"], "a/keep.txt", None)]
+    #[case(&["!a"], "a/keep.txt", Some("a is excluded by glob \"!a\""))]
+    #[case(&["*.txt"], "a/skip.log", Some("not matched by any include glob"))]
+    #[case(&["*.txt"], "a/keep.txt", None)]
+    fn globs_exclude(
+        #[case] globs: &[&str],
+        #[case] path: &str,
+        #[case] expected: Option<&str>,
+    ) -> Result<()> {
+        let opts = LocalSourceFilterOptions::default().globs(
+            globs
+                .iter()
+                .map(|glob| (*glob).to_string())
+                .collect::<Vec<_>>(),
+        );
+        check(&opts, path, expected)
+    }
+
+    #[rstest]
+    #[case("a/skip.log", Some("excluded by \"*.log\""))]
+    #[case("a/important.log", None)]
+    #[case("a/keep.txt", None)]
+    fn gitignore_excludes(#[case] path: &str, #[case] expected: Option<&str>) -> Result<()> {
+        let opts = LocalSourceFilterOptions::default()
+            .git_ignore(true)
+            .no_require_git(true);
+        check(&opts, path, expected)
+    }
+
+    #[rstest]
+    #[case("cache/data.txt", Some("cache is excluded by exclude-if-present"))]
+    #[case("a/keep.txt", None)]
+    fn exclude_if_present_excludes_parent(
+        #[case] path: &str,
+        #[case] expected: Option<&str>,
+    ) -> Result<()> {
+        let opts = LocalSourceFilterOptions::default()
+            .exclude_if_present(vec!["CACHEDIR.TAG".to_string()]);
+        check(&opts, path, expected)
+    }
+
+    #[rstest]
+    #[case("big.txt", Some("larger than exclude-larger-than"))]
+    #[case("small.txt", None)]
+    fn exclude_larger_than_excludes(
+        #[case] path: &str,
+        #[case] expected: Option<&str>,
+    ) -> Result<()> {
+        let opts = LocalSourceFilterOptions::default().exclude_larger_than(ByteSize::b(100));
+        check(&opts, path, expected)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn one_file_system_keeps_same_file_system() -> Result<()> {
+        let opts = LocalSourceFilterOptions::default().one_file_system(true);
+        check(&opts, "a/keep.txt", None)
+    }
+}
@@ -0,0 +1,203 @@
+//! `cache` subcommand
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backend::datacache::DATA_RANGES_DIR,
+    commands::index_paths::PATH_INDEX_DIR,
+    helpers::{bytes_size_to_string, table_right_from},
+    status_err, Application, RUSTIC_APP,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, Result};
+use log::info;
+
+use rustic_core::FileType;
+
+/// `cache` subcommand
+///
+/// Show or clear the contents of the cache directory
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct CacheCmd {
+    #[clap(subcommand)]
+    cmd: CacheSubCmd,
+}
+
+/// `cache` subcommands
+#[derive(clap::Subcommand, Debug)]
+enum CacheSubCmd {
+    /// Show the number of entries and the size of all cached files per repository and file type
+    Stats,
+    /// Remove cached files
+    Clear(ClearOpts),
+}
+
+#[derive(clap::Parser, Debug)]
+struct ClearOpts {
+    /// Only remove the cache of the given repository ids (can be specified multiple times)
+    #[clap(long = "repo-id", value_name = "ID")]
+    repo_ids: Vec<String>,
+
+    /// Only remove cached files of the given type (can be specified multiple times)
+    #[clap(long = "type", value_name = "TYPE", value_enum)]
+    types: Vec<CacheFileType>,
+}
+
+/// File types which are saved in the cache
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum CacheFileType {
+    Snapshot,
+    Index,
+    Pack,
+    /// The path index built by `index-paths build`
+    Paths,
+    /// Ranges of data packs saved by `restore --cache-data`
+    DataRanges,
+}
+
+impl CacheFileType {
+    const ALL: [Self; 5] = [
+        Self::Snapshot,
+        Self::Index,
+        Self::Pack,
+        Self::Paths,
+        Self::DataRanges,
+    ];
+
+    /// Name of the directory containing the cached files of this type
+    fn dirname(self) -> &'static str {
+        match self {
+            Self::Snapshot => FileType::Snapshot.dirname(),
+            Self::Index => FileType::Index.dirname(),
+            Self::Pack => FileType::Pack.dirname(),
+            Self::Paths => PATH_INDEX_DIR,
+            Self::DataRanges => DATA_RANGES_DIR,
+        }
+    }
+}
+
+impl Runnable for CacheCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Get the number of files and the total size of all files within a directory
+fn dir_stats(path: &Path) -> Result<(u64, u64)> {
+    let mut stats = (0, 0);
+    if !path.is_dir() {
+        return Ok(stats);
+    }
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            let (count, size) = dir_stats(&entry.path())?;
+            stats.0 += count;
+            stats.1 += size;
+        } else {
+            stats.0 += 1;
+            stats.1 += meta.len();
+        }
+    }
+    Ok(stats)
+}
+
+/// Get the subdirectories of the cache directory, i.e. the caches of the repositories
+fn cache_subdirs(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push((
+                entry.file_name().to_string_lossy().to_string(),
+                entry.path(),
+            ));
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+impl CacheCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let dir = config
+            .repository
+            .cache_dir()
+            .ok_or_else(|| anyhow!("no cache directory available."))?;
+
+        match &self.cmd {
+            CacheSubCmd::Stats => stats(&dir),
+            CacheSubCmd::Clear(opts) => opts.clear(&dir, config.global.dry_run),
+        }
+    }
+}
+
+/// Print statistics about the cache
+///
+/// # Arguments
+///
+/// * `dir` - The cache directory
+fn stats(dir: &Path) -> Result<()> {
+    println!("cache directory: {}", dir.display());
+    let mut table = table_right_from(2, ["Repository", "File type", "Count", "Total Size"]);
+    for (name, path) in cache_subdirs(dir)? {
+        for tpe in CacheFileType::ALL {
+            let (count, size) = dir_stats(&path.join(tpe.dirname()))?;
+            _ = table.add_row([
+                name.clone(),
+                format!("{tpe:?}"),
+                count.to_string(),
+                bytes_size_to_string(size),
+            ]);
+        }
+    }
+    println!("{table}");
+    Ok(())
+}
+
+impl ClearOpts {
+    /// Remove the selected cached files
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The cache directory
+    /// * `dry_run` - Only show which directories would be removed
+    fn clear(&self, dir: &Path, dry_run: bool) -> Result<()> {
+        let types = if self.types.is_empty() {
+            CacheFileType::ALL.to_vec()
+        } else {
+            self.types.clone()
+        };
+        for (name, path) in cache_subdirs(dir)? {
+            if !self.repo_ids.is_empty() && !self.repo_ids.contains(&name) {
+                continue;
+            }
+            let paths: Vec<_> = types
+                .iter()
+                .map(|tpe| path.join(tpe.dirname()))
+                .filter(|path| path.is_dir())
+                .collect();
+            for path in paths {
+                if dry_run {
+                    info!("would remove {}", path.display());
+                } else {
+                    info!("removing {}...", path.display());
+                    fs::remove_dir_all(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
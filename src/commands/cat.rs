@@ -1,5 +1,7 @@
 //! `cat` subcommand
 
+use std::io::Write;
+
 use crate::{
     commands::{open_repository, open_repository_indexed},
     status_err, Application, RUSTIC_APP,
@@ -7,7 +9,8 @@ use crate::{
 
 use abscissa_core::{Command, Runnable, Shutdown};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use log::info;
 
 use rustic_core::repofile::{BlobType, FileType};
 
@@ -26,7 +29,7 @@ enum CatSubCmd {
     /// Display a tree blob
     TreeBlob(IdOpt),
     /// Display a data blob
-    DataBlob(IdOpt),
+    DataBlob(DataBlobOpts),
     /// Display the config file
     Config,
     /// Display an index file
@@ -43,6 +46,21 @@ struct IdOpt {
     id: String,
 }
 
+#[derive(Default, clap::Parser, Debug)]
+struct DataBlobOpts {
+    /// Id to display
+    #[clap(required_unless_present = "of_file", conflicts_with = "of_file")]
+    id: Option<String>,
+
+    /// Display a content blob of this file instead
+    #[clap(long, value_name = "SNAPSHOT:PATH", requires = "index")]
+    of_file: Option<String>,
+
+    /// Position of the blob within the file contents (starting with 0)
+    #[clap(long, value_name = "N", requires = "of_file")]
+    index: Option<usize>,
+}
+
 #[derive(clap::Parser, Debug)]
 struct TreeOpts {
     /// Snapshot/path of the tree to display
@@ -62,24 +80,46 @@ impl Runnable for CatCmd {
 impl CatCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
-        let data =
-            match &self.cmd {
-                CatSubCmd::Config => {
-                    open_repository(&config.repository)?.cat_file(FileType::Config, "")?
-                }
-                CatSubCmd::Index(opt) => {
-                    open_repository(&config.repository)?.cat_file(FileType::Index, &opt.id)?
-                }
-                CatSubCmd::Snapshot(opt) => {
-                    open_repository(&config.repository)?.cat_file(FileType::Snapshot, &opt.id)?
-                }
-                CatSubCmd::TreeBlob(opt) => open_repository_indexed(&config.repository)?
-                    .cat_blob(BlobType::Tree, &opt.id)?,
-                CatSubCmd::DataBlob(opt) => open_repository_indexed(&config.repository)?
-                    .cat_blob(BlobType::Data, &opt.id)?,
-                CatSubCmd::Tree(opt) => open_repository_indexed(&config.repository)?
-                    .cat_tree(&opt.snap, |sn| config.snapshot_filter.matches(sn))?,
-            };
+        let data = match &self.cmd {
+            CatSubCmd::Config => {
+                open_repository(&config.repository)?.cat_file(FileType::Config, "")?
+            }
+            CatSubCmd::Index(opt) => {
+                open_repository(&config.repository)?.cat_file(FileType::Index, &opt.id)?
+            }
+            CatSubCmd::Snapshot(opt) => {
+                open_repository(&config.repository)?.cat_file(FileType::Snapshot, &opt.id)?
+            }
+            CatSubCmd::TreeBlob(opt) => {
+                open_repository_indexed(&config.repository)?.cat_blob(BlobType::Tree, &opt.id)?
+            }
+            CatSubCmd::DataBlob(opt) => {
+                let repo = open_repository_indexed(&config.repository)?;
+                let id = match (&opt.id, &opt.of_file, opt.index) {
+                    (Some(id), _, _) => id.clone(),
+                    (None, Some(file), Some(index)) => {
+                        let node = repo.node_from_snapshot_path(file, |sn| {
+                            config.snapshot_filter.matches(sn)
+                        })?;
+                        let content = node
+                            .content
+                            .ok_or_else(|| anyhow!("{file} is not a file"))?;
+                        let id = content
+                            .get(index)
+                            .ok_or_else(|| anyhow!("{file} only has {} blobs", content.len()))?;
+                        info!("blob {index} of {file} is {}", id.to_hex());
+                        id.to_hex().to_string()
+                    }
+                    _ => bail!("either an id or --of-file and --index must be given"),
+                };
+                // data blobs may be binary, so output them unchanged
+                let data = repo.cat_blob(BlobType::Data, &id)?;
+                std::io::stdout().write_all(&data)?;
+                return Ok(());
+            }
+            CatSubCmd::Tree(opt) => open_repository_indexed(&config.repository)?
+                .cat_tree(&opt.snap, |sn| config.snapshot_filter.matches(sn))?,
+        };
         println!("{}", String::from_utf8(data.to_vec())?);
 
         Ok(())
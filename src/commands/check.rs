@@ -1,10 +1,28 @@
 //! `check` subcommand
 
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    time::Instant,
+};
+
+use crate::{
+    backend::hotcold::HotColdRepairPlan,
+    commands::{get_repository_with_backends, open_repository, open_with_password},
+    config::progress_options::PhaseProgressBars,
+    helpers::ResourceUsage,
+    status_err, Application, RusticConfig, RUSTIC_APP,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use rustic_core::CheckOptions;
+use anyhow::{bail, Result};
+use log::{debug, error, info, log, warn, Level};
+use rustic_core::{
+    repofile::{BlobType, IndexFile},
+    BlobId, CheckOptions, CopySnapshot, OpenStatus, Progress, ProgressBars, Repository,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
 /// `check` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -12,22 +30,256 @@ pub(crate) struct CheckCmd {
     /// Check options
     #[clap(flatten)]
     opts: CheckOptions,
+
+    /// Repair inconsistencies between hot and cold repository: Copy missing files to the hot
+    /// repository and, with --exclusive, remove orphaned files from the hot repository
+    #[clap(long)]
+    fix: bool,
+
+    /// Confirm that no other process uses the repository, such that --fix may remove orphaned
+    /// files from the hot repository. Warning: A concurrent backup writes to the hot repository
+    /// first, so its new files would be removed!
+    #[clap(long, requires = "fix")]
+    exclusive: bool,
+
+    /// Print a json line to stdout whenever a check phase starts or finishes, including timings,
+    /// and a json line with the used resources at the end
+    #[clap(long)]
+    json_phases: bool,
+
+    /// Check that the repository given by this profile contains all snapshots and blobs of the
+    /// repository, e.g. to verify a replica created by copy
+    #[clap(long, value_name = "PROFILE")]
+    against: Option<String>,
+
+    /// Read this many blobs (evenly distributed) from the repository given by --against and
+    /// verify their contents
+    #[clap(long, value_name = "N", requires = "against")]
+    against_sample: Option<usize>,
 }
 
 impl Runnable for CheckCmd {
     fn run(&self) {
+        let start = Instant::now();
         if let Err(err) = self.inner_run() {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
+        let resources = ResourceUsage::since(start);
+        resources.log();
+        if self.json_phases {
+            let event = ResourcesEvent {
+                event: "resources",
+                resources,
+            };
+            match serde_json::to_string(&event) {
+                Ok(event) => println!("{event}"),
+                Err(err) => warn!("error serializing resources event: {err}"),
+            }
+        }
     }
 }
 
+/// A json event about the used resources, printed at the end of `check --json-phases`
+#[derive(Serialize)]
+struct ResourcesEvent {
+    event: &'static str,
+    #[serde(flatten)]
+    resources: ResourceUsage,
+}
+
 impl CheckCmd {
     fn inner_run(&self) -> Result<()> {
+        let po = RUSTIC_APP.config().global.progress_options;
+        if self.json_phases {
+            self.check(PhaseProgressBars(po))
+        } else {
+            self.check(po)
+        }
+    }
+
+    fn check<P: ProgressBars + Clone>(&self, po: P) -> Result<()> {
         let config = RUSTIC_APP.config();
-        let repo = open_repository(&config.repository)?;
+        let backends = config.repository.to_backends()?;
+        let repo = get_repository_with_backends(&config.repository, &backends, po)?;
+        let repo = open_with_password(repo)?;
         repo.check(self.opts)?;
+
+        if let Some(against) = &self.against {
+            check_against(&repo, against, self.against_sample)?;
+        }
+
+        let Some(be_hot) = backends.repo_hot() else {
+            return Ok(());
+        };
+
+        let mut tree_packs = BTreeSet::new();
+        for item in repo.stream_files::<IndexFile>()? {
+            let (_, index) = item?;
+            tree_packs.extend(
+                index
+                    .packs
+                    .iter()
+                    .filter(|pack| pack.blob_type() == BlobType::Tree)
+                    .map(|pack| *pack.id),
+            );
+        }
+
+        let be = backends.repository();
+        let mut plan = HotColdRepairPlan::new(&be, &be_hot, &tree_packs)?;
+        if plan.is_empty() {
+            info!("hot repository is consistent.");
+            return Ok(());
+        }
+
+        println!("repair plan for hot repository:");
+        plan.print();
+        match (self.fix, config.global.dry_run) {
+            (false, _) => info!("run check with --fix to repair the hot repository."),
+            (true, true) => info!("would have repaired the hot repository."),
+            (true, false) => {
+                if !self.exclusive && !plan.orphaned.is_empty() {
+                    warn!(
+                        "not removing {} orphaned files from the hot repository, they may belong to a running backup. Use --exclusive to remove them.",
+                        plan.orphaned.len()
+                    );
+                    plan.orphaned.clear();
+                }
+                plan.execute(&be, &be_hot)?;
+                info!("hot repository repaired.");
+            }
+        }
         Ok(())
     }
 }
+
+/// Get all blobs contained in the index of a repository
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+fn indexed_blobs<P: ProgressBars>(
+    repo: &Repository<P, OpenStatus>,
+) -> Result<BTreeMap<BlobId, BlobType>> {
+    let mut blobs = BTreeMap::new();
+    for item in repo.stream_files::<IndexFile>()? {
+        let (_, index) = item?;
+        for pack in index.packs {
+            let tpe = pack.blob_type();
+            blobs.extend(pack.blobs.into_iter().map(|blob| (blob.id, tpe)));
+        }
+    }
+    Ok(blobs)
+}
+
+/// Number of problems of one kind which are listed by `check --against`, further ones are only
+/// logged with debug level
+const MAX_LISTED: usize = 10;
+
+/// Log a problem found by `check --against`
+///
+/// # Arguments
+///
+/// * `i` - The number of problems of the same kind which have already been logged
+/// * `msg` - The problem
+fn log_listed(i: usize, msg: &str) {
+    match i.cmp(&MAX_LISTED) {
+        Ordering::Less => error!("{msg}"),
+        Ordering::Equal => {
+            error!("further problems of this kind are only shown with --log-level debug");
+            debug!("{msg}");
+        }
+        Ordering::Greater => debug!("{msg}"),
+    }
+}
+
+/// Check that another repository contains all snapshots and blobs of the repository
+///
+/// Snapshots are compared like `copy` does, i.e. by their contents and not by their ids.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `against` - The profile of the other repository
+/// * `sample` - The number of blobs to read from the other repository and verify
+fn check_against<P: ProgressBars>(
+    repo: &Repository<P, OpenStatus>,
+    against: &str,
+    sample: Option<usize>,
+) -> Result<()> {
+    let po = RUSTIC_APP.config().global.progress_options;
+
+    let mut merge_logs = Vec::new();
+    let mut other_config = RusticConfig::default();
+    other_config.merge_profile(against, &mut merge_logs, Level::Error)?;
+    // display logs from merging
+    for (level, merge_log) in merge_logs {
+        log!(level, "{}", merge_log);
+    }
+    let other = open_repository(&other_config.repository)?;
+    let name = other.name.clone();
+    info!("checking against repository {name}...");
+
+    let snapshots = repo.get_all_snapshots()?;
+    let missing_snaps = other.relevant_copy_snapshots(|_| true, &snapshots)?;
+    let missing_snaps: Vec<_> = missing_snaps
+        .iter()
+        .filter_map(|CopySnapshot { relevant, sn }| relevant.then_some(sn))
+        .collect();
+    for (i, sn) in missing_snaps.iter().enumerate() {
+        log_listed(i, &format!("snapshot {} is missing in {name}", sn.id));
+    }
+
+    let p = po.progress_spinner("comparing blobs...");
+    let blobs = indexed_blobs(repo)?;
+    let other_blobs = indexed_blobs(&other)?;
+    p.finish();
+    let (mut missing_trees, mut missing_data) = (0, 0);
+    for (id, tpe) in blobs.iter().filter(|(id, _)| !other_blobs.contains_key(id)) {
+        let count = match tpe {
+            BlobType::Tree => &mut missing_trees,
+            BlobType::Data => &mut missing_data,
+        };
+        log_listed(*count, &format!("{tpe:?} blob {id} is missing in {name}"));
+        *count += 1;
+    }
+
+    let mut damaged_blobs = 0;
+    if let Some(n) = sample.filter(|n| *n > 0) {
+        let other = other.to_indexed()?;
+        let step = (other_blobs.len() / n).max(1);
+        let p = po.progress_counter("reading sampled blobs...");
+        p.set_length(other_blobs.len().div_ceil(step).try_into()?);
+        for (id, tpe) in other_blobs.iter().step_by(step) {
+            let id = id.to_hex().to_string();
+            match other.cat_blob(*tpe, &id) {
+                Ok(data) if format!("{:x}", Sha256::digest(&data)) == id => {}
+                Ok(_) => {
+                    log_listed(
+                        damaged_blobs,
+                        &format!("{tpe:?} blob {id} in {name} has wrong contents"),
+                    );
+                    damaged_blobs += 1;
+                }
+                Err(err) => {
+                    log_listed(
+                        damaged_blobs,
+                        &format!("error reading {tpe:?} blob {id} from {name}: {err}"),
+                    );
+                    damaged_blobs += 1;
+                }
+            }
+            p.inc(1);
+        }
+        p.finish();
+    }
+
+    if !missing_snaps.is_empty() || missing_trees > 0 || missing_data > 0 || damaged_blobs > 0 {
+        bail!(
+            "{name} is not complete: {} snapshots missing, {missing_trees} tree blobs missing, {missing_data} data blobs missing, {damaged_blobs} sampled blobs damaged.",
+            missing_snaps.len()
+        );
+    }
+    info!("{name} contains all snapshots and blobs.");
+    Ok(())
+}
@@ -10,10 +10,20 @@ use rustic_core::ConfigOptions;
 
 /// `config` subcommand
 #[derive(clap::Parser, Command, Debug)]
+#[clap(args_conflicts_with_subcommands = true)]
 pub(crate) struct ConfigCmd {
     /// Config options
     #[clap(flatten)]
     config_opts: ConfigOptions,
+
+    #[clap(subcommand)]
+    cmd: Option<ConfigSubCmd>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ConfigSubCmd {
+    /// Validate the rustic config file(s) without accessing the repository
+    Validate,
 }
 
 impl Runnable for ConfigCmd {
@@ -26,16 +36,30 @@ impl Runnable for ConfigCmd {
 }
 
 impl ConfigCmd {
+    /// Returns whether the config is validated
+    pub(crate) fn is_validate(&self) -> bool {
+        matches!(self.cmd, Some(ConfigSubCmd::Validate))
+    }
+
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
-        let repo = open_repository(&config.repository)?;
 
-        let changed = repo.apply_config(&self.config_opts)?;
+        match self.cmd {
+            Some(ConfigSubCmd::Validate) => {
+                config.validate()?;
+                println!("config is valid");
+            }
+            None => {
+                let repo = open_repository(&config.repository)?;
+
+                let changed = repo.apply_config(&self.config_opts)?;
 
-        if changed {
-            println!("saved new config");
-        } else {
-            println!("config is unchanged");
+                if changed {
+                    println!("saved new config");
+                } else {
+                    println!("config is unchanged");
+                }
+            }
         }
 
         Ok(())
@@ -2,6 +2,7 @@
 
 use crate::{
     commands::{get_repository, init::init_password, open_repository, open_repository_indexed},
+    filtering::keep_latest,
     helpers::table_with_titles,
     status_err, Application, RusticConfig, RUSTIC_APP,
 };
@@ -11,7 +12,7 @@ use log::{error, info, log, Level};
 use merge::Merge;
 use serde::{Deserialize, Serialize};
 
-use rustic_core::{CopySnapshot, Id, KeyOptions};
+use rustic_core::{CopySnapshot, Id, KeyOptions, SnapshotGroupCriterion};
 
 /// `copy` subcommand
 #[derive(clap::Parser, Command, Default, Clone, Debug, Serialize, Deserialize, Merge)]
@@ -28,6 +29,23 @@ pub struct CopyCmd {
     #[merge(skip)]
     init: bool,
 
+    /// Only copy the latest N snapshots of each group
+    #[clap(long, value_name = "N")]
+    #[serde(skip)]
+    #[merge(skip)]
+    latest: Option<usize>,
+
+    /// Group snapshots by any combination of host,label,paths,tags (when using --latest)
+    #[clap(
+        long,
+        short = 'g',
+        value_name = "CRITERION",
+        default_value = "host,label,paths"
+    )]
+    #[serde(skip)]
+    #[merge(skip)]
+    group_by: SnapshotGroupCriterion,
+
     /// Target repository (can be specified multiple times)
     #[clap(long = "target", value_name = "TARGET")]
     #[merge(strategy = merge::vec::overwrite_empty)]
@@ -71,7 +89,13 @@ impl CopyCmd {
         }
 
         let repo = open_repository_indexed(&config.repository)?;
-        let mut snapshots = if self.ids.is_empty() {
+        let mut snapshots = if let Some(n) = self.latest {
+            let mut groups = repo.get_snapshot_group(&self.ids, self.group_by, |sn| {
+                config.snapshot_filter.matches(sn)
+            })?;
+            keep_latest(&mut groups, n);
+            groups.into_iter().flat_map(|(_, sns)| sns).collect()
+        } else if self.ids.is_empty() {
             repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
         } else {
             repo.get_snapshots(&self.ids)?
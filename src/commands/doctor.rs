@@ -0,0 +1,282 @@
+//! `doctor` subcommand
+
+use std::{fmt, fs, path::Path, time::Instant};
+
+use crate::{
+    commands::{get_repository_with_backends, open_with_password},
+    helpers::{bytes_size_to_string, table_with_titles},
+    status_err, Application, RUSTIC_APP,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use chrono::{Duration, Local};
+
+use rustic_core::{FileType, ReadBackend};
+
+/// Backend operations taking longer than this are reported as slow
+const SLOW_BACKEND_SECS: f64 = 2.0;
+
+/// Maximum difference between the local time and snapshot times which is accepted
+const MAX_CLOCK_SKEW_MINUTES: i64 = 5;
+
+/// Free space in the cache or a local repository below which a warning is reported
+const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+
+/// `doctor` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct DoctorCmd {}
+
+impl Runnable for DoctorCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Result of a single check
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "ok",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// Results of all checks which have been run
+#[derive(Default)]
+struct Report(Vec<(&'static str, Status, String)>);
+
+impl Report {
+    fn add(&mut self, check: &'static str, status: Status, details: impl Into<String>) {
+        self.0.push((check, status, details.into()));
+    }
+
+    fn count(&self, status: Status) -> usize {
+        self.0.iter().filter(|(_, s, _)| *s == status).count()
+    }
+}
+
+impl DoctorCmd {
+    fn inner_run(&self) -> Result<()> {
+        let mut report = Report::default();
+        run_checks(&mut report);
+
+        let mut table = table_with_titles(["Check", "Status", "Details"]);
+        for (check, status, details) in &report.0 {
+            _ = table.add_row([check.to_string(), status.to_string(), details.clone()]);
+        }
+        println!("{table}");
+
+        let (warnings, errors) = (report.count(Status::Warning), report.count(Status::Error));
+        if errors > 0 {
+            bail!("{errors} checks failed, {warnings} warnings.");
+        }
+        println!("all checks passed, {warnings} warnings.");
+        Ok(())
+    }
+}
+
+/// Run all checks and add their results to the report
+///
+/// Checks which need a previous check to succeed are skipped if it failed.
+///
+/// # Arguments
+///
+/// * `report` - The report to add the results to
+fn run_checks(report: &mut Report) {
+    let config = RUSTIC_APP.config();
+
+    // configuration and backends
+    match config.validate() {
+        Ok(()) => report.add("config", Status::Ok, "config files are valid"),
+        Err(err) => report.add("config", Status::Error, err.to_string()),
+    }
+    let backends = match config.repository.to_backends() {
+        Ok(backends) => {
+            report.add("config", Status::Ok, "repository options are valid");
+            backends
+        }
+        Err(err) => {
+            report.add("config", Status::Error, err.to_string());
+            return;
+        }
+    };
+    if config.global.read_only {
+        report.add("config", Status::Warning, "repository is opened read-only");
+    }
+
+    let mut keys_found = false;
+    for (check, be) in [
+        ("backend", Some(backends.repository())),
+        ("hot backend", backends.repo_hot()),
+    ] {
+        let Some(be) = be else {
+            continue;
+        };
+        let start = Instant::now();
+        match be.list(FileType::Key) {
+            Ok(keys) => {
+                let secs = start.elapsed().as_secs_f64();
+                let status = if secs > SLOW_BACKEND_SECS {
+                    Status::Warning
+                } else {
+                    Status::Ok
+                };
+                report.add(
+                    check,
+                    status,
+                    format!("{} reachable, listing took {secs:.2}s", be.location()),
+                );
+                keys_found |= !keys.is_empty();
+            }
+            Err(err) => {
+                report.add(check, Status::Error, format!("{}: {err}", be.location()));
+                return;
+            }
+        }
+    }
+    if !keys_found {
+        report.add(
+            "keys",
+            Status::Error,
+            "no keys found, is the repository initialized?",
+        );
+        return;
+    }
+
+    // keys and repository config
+    let repo = match get_repository_with_backends(&config.repository, &backends, ())
+        .and_then(open_with_password)
+    {
+        Ok(repo) => {
+            report.add("keys", Status::Ok, "repository key can be opened");
+            repo
+        }
+        Err(err) => {
+            report.add("keys", Status::Error, err.to_string());
+            return;
+        }
+    };
+    let repo_config = repo.config();
+    match repo_config.version {
+        1 | 2 => report.add(
+            "version",
+            Status::Ok,
+            format!("repository version {}", repo_config.version),
+        ),
+        version => report.add(
+            "version",
+            Status::Error,
+            format!("unsupported repository version {version}"),
+        ),
+    }
+
+    // cache
+    let cache_dir = config
+        .repository
+        .cache_dir()
+        .map(|dir| dir.join(repo_config.id.to_hex().to_string()));
+    match &cache_dir {
+        None => report.add("cache", Status::Ok, "cache is disabled"),
+        Some(dir) => match check_writable(dir) {
+            Ok(()) => report.add("cache", Status::Ok, dir.display().to_string()),
+            Err(err) => report.add(
+                "cache",
+                Status::Warning,
+                format!("{} is not writable: {err}", dir.display()),
+            ),
+        },
+    }
+
+    // free space
+    for dir in cache_dir
+        .into_iter()
+        .chain(config.repository.local_repo_dirs())
+    {
+        match free_space(&dir) {
+            Ok(free) if free < MIN_FREE_SPACE => report.add(
+                "free space",
+                Status::Warning,
+                format!(
+                    "only {} free in {}",
+                    bytes_size_to_string(free),
+                    dir.display()
+                ),
+            ),
+            Ok(free) => report.add(
+                "free space",
+                Status::Ok,
+                format!("{} free in {}", bytes_size_to_string(free), dir.display()),
+            ),
+            Err(err) => report.add(
+                "free space",
+                Status::Warning,
+                format!("{}: {err}", dir.display()),
+            ),
+        }
+    }
+
+    // clock sanity
+    match repo.get_all_snapshots() {
+        Ok(snaps) => {
+            let limit = Local::now() + Duration::minutes(MAX_CLOCK_SKEW_MINUTES);
+            match snaps
+                .iter()
+                .filter(|sn| sn.time > limit)
+                .max_by_key(|sn| sn.time)
+            {
+                Some(sn) => report.add(
+                    "clock",
+                    Status::Warning,
+                    format!(
+                        "snapshot {} has time {} in the future, check the clocks",
+                        sn.id,
+                        sn.time.format("%Y-%m-%d %H:%M:%S")
+                    ),
+                ),
+                None => report.add(
+                    "clock",
+                    Status::Ok,
+                    format!("no snapshot in the future ({} snapshots)", snaps.len()),
+                ),
+            }
+        }
+        Err(err) => report.add("snapshots", Status::Error, err.to_string()),
+    }
+}
+
+/// Check that files can be created in a directory by creating and removing a file
+///
+/// The directory is created if it doesn't exist.
+fn check_writable(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let file = dir.join(format!(".rustic-doctor-{}", std::process::id()));
+    _ = fs::File::create(&file)?;
+    fs::remove_file(&file)?;
+    Ok(())
+}
+
+/// Get the space available to the current user in the file system containing `path`
+#[cfg(not(windows))]
+fn free_space(path: &Path) -> Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(path)?;
+    Ok(u64::from(stat.blocks_available()) * u64::from(stat.fragment_size()))
+}
+
+/// Get the space available to the current user in the file system containing `path`
+#[cfg(windows)]
+fn free_space(_path: &Path) -> Result<u64> {
+    bail!("checking the free space is not supported on windows")
+}
@@ -0,0 +1,219 @@
+//! `du` subcommand
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    commands::open_repository_indexed,
+    helpers::{bytes_size_to_string, table_right_from},
+    status_err, Application, RUSTIC_APP,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use serde::Serialize;
+
+use rustic_core::{
+    repofile::Node, DataId, IndexedFull, PackedId, Progress, ProgressBars, Repository, TreeId,
+};
+
+/// `du` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct DuCmd {
+    /// Snapshot/path to summarize
+    #[clap(value_name = "SNAPSHOT[:PATH]")]
+    snap: String,
+
+    /// Show infos in json format
+    #[clap(long)]
+    json: bool,
+}
+
+impl Runnable for DuCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Disk usage of a snapshot/path
+///
+/// This struct is used to serialize infos in `json` format.
+#[derive(Default, Serialize)]
+struct DuInfos {
+    /// Number of files
+    files: u64,
+    /// Sum of the file sizes
+    apparent_size: u64,
+    /// Number of distinct blobs
+    blobs: u64,
+    /// Stored (deduplicated and compressed) size of all blobs
+    stored_size: u64,
+    /// Number of blobs which are also used by other snapshots
+    shared_blobs: u64,
+    /// Stored size of blobs which are also used by other snapshots
+    shared_size: u64,
+}
+
+/// Blobs referenced by some trees
+#[derive(Default)]
+pub(crate) struct Blobs {
+    pub(crate) trees: BTreeSet<TreeId>,
+    pub(crate) data: BTreeSet<DataId>,
+    /// Number of files and their total size within each added tree
+    file_sizes: BTreeMap<TreeId, (u64, u64)>,
+}
+
+impl Blobs {
+    /// Add the given tree and all subtrees. For each node found, `f` is called.
+    ///
+    /// Trees which have already been added are not traversed again, but their files are counted
+    /// for every occurrence.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository
+    /// * `id` - The id of the tree to add
+    /// * `f` - The function to call for each node
+    ///
+    /// # Returns
+    ///
+    /// The number of files and their total size within the tree
+    fn add_tree<P, S: IndexedFull>(
+        &mut self,
+        repo: &Repository<P, S>,
+        id: TreeId,
+        f: &mut impl FnMut(&Node),
+    ) -> Result<(u64, u64)> {
+        if let Some(sizes) = self.file_sizes.get(&id) {
+            return Ok(*sizes);
+        }
+        let (mut files, mut size) = (0, 0);
+        for node in repo.get_tree(&id)?.nodes {
+            f(&node);
+            if node.is_file() {
+                files += 1;
+                size += node.meta.size;
+            }
+            self.add_node(&node);
+            if let Some(subtree) = node.subtree {
+                let (sub_files, sub_size) = self.add_tree(repo, subtree, f)?;
+                files += sub_files;
+                size += sub_size;
+            }
+        }
+        _ = self.trees.insert(id);
+        _ = self.file_sizes.insert(id, (files, size));
+        Ok((files, size))
+    }
+
+    /// Add the data blobs of the given node
+    pub(crate) fn add_node(&mut self, node: &Node) {
+        if let Some(content) = &node.content {
+            self.data.extend(content);
+        }
+    }
+
+    /// Get the number and the stored size of all blobs
+    fn size<P, S: IndexedFull>(&self, repo: &Repository<P, S>) -> Result<(u64, u64)> {
+        fn size_of<P, S: IndexedFull, T: PackedId>(
+            repo: &Repository<P, S>,
+            ids: &BTreeSet<T>,
+        ) -> Result<u64> {
+            let mut size = 0;
+            for id in ids {
+                size += u64::from(repo.get_index_entry(id)?.length);
+            }
+            Ok(size)
+        }
+        let count = self.trees.len() + self.data.len();
+        let size = size_of(repo, &self.trees)? + size_of(repo, &self.data)?;
+        Ok((count.try_into()?, size))
+    }
+}
+
+impl DuCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository_indexed(&config.repository)?;
+
+        let (id, path) = self.snap.split_once(':').unwrap_or((&self.snap, ""));
+        let snap = repo.get_snapshot_from_str(id, |sn| config.snapshot_filter.matches(sn))?;
+        let node = repo.node_from_snapshot_and_path(&snap, path)?;
+
+        // collect blobs of the given snapshot/path
+        let mut infos = DuInfos::default();
+        let mut blobs = Blobs::default();
+        if node.is_file() {
+            infos.files = 1;
+            infos.apparent_size = node.meta.size;
+        }
+        blobs.add_node(&node);
+        if let Some(id) = node.subtree {
+            (infos.files, infos.apparent_size) = blobs.add_tree(&repo, id, &mut |_| {})?;
+        }
+        (infos.blobs, infos.stored_size) = blobs.size(&repo)?;
+
+        // find blobs which are also used by other snapshots
+        let snaps: Vec<_> = repo
+            .get_all_snapshots()?
+            .into_iter()
+            .filter(|sn| sn.id != snap.id)
+            .collect();
+        let p = config
+            .global
+            .progress_options
+            .progress_counter("scanning other snapshots...");
+        p.set_length(snaps.len().try_into()?);
+        let mut other_blobs = Blobs::default();
+        let mut shared = Blobs::default();
+        for sn in snaps {
+            _ = other_blobs.add_tree(&repo, sn.tree, &mut |node| {
+                if let Some(content) = &node.content {
+                    shared
+                        .data
+                        .extend(content.iter().filter(|id| blobs.data.contains(id)));
+                }
+            })?;
+            p.inc(1);
+        }
+        p.finish();
+        shared.trees = blobs
+            .trees
+            .intersection(&other_blobs.trees)
+            .copied()
+            .collect();
+        (infos.shared_blobs, infos.shared_size) = shared.size(&repo)?;
+
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &infos)?;
+            return Ok(());
+        }
+
+        let mut table = table_right_from(1, ["", "Count", "Size"]);
+        _ = table.add_row([
+            "apparent size (files)".to_string(),
+            infos.files.to_string(),
+            bytes_size_to_string(infos.apparent_size),
+        ]);
+        _ = table.add_row([
+            "stored size (blobs)".to_string(),
+            infos.blobs.to_string(),
+            bytes_size_to_string(infos.stored_size),
+        ]);
+        _ = table.add_row([
+            "shared with other snapshots".to_string(),
+            infos.shared_blobs.to_string(),
+            bytes_size_to_string(infos.shared_size),
+        ]);
+        _ = table.add_row([
+            "only in this snapshot".to_string(),
+            (infos.blobs - infos.shared_blobs).to_string(),
+            bytes_size_to_string(infos.stored_size - infos.shared_size),
+        ]);
+        println!("{table}");
+        Ok(())
+    }
+}
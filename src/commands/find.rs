@@ -2,13 +2,20 @@
 
 use std::path::{Path, PathBuf};
 
-use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_APP};
+use crate::{
+    commands::{
+        index_paths::{path_index_dir, read_path_index},
+        open_repository_indexed,
+    },
+    status_err, Application, RUSTIC_APP,
+};
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::ValueHint;
 use globset::{Glob, GlobBuilder, GlobSetBuilder};
 use itertools::Itertools;
+use regex::RegexSet;
 
 use rustic_core::{
     repofile::{Node, SnapshotFile},
@@ -28,6 +35,11 @@ pub(crate) struct FindCmd {
     #[clap(long, value_name = "PATTERN", conflicts_with = "path")]
     iglob: Vec<String>,
 
+    /// regular expression to find, matched against the path and the file name (can be specified
+    /// multiple times)
+    #[clap(long, value_name = "REGEX", conflicts_with = "path")]
+    regex: Vec<String>,
+
     /// exact path to find
     #[clap(long, value_name = "PATH", value_hint = ValueHint::AnyPath)]
     path: Option<PathBuf>,
@@ -56,6 +68,11 @@ pub(crate) struct FindCmd {
     /// Show uid/gid instead of user/group
     #[clap(long, long("numeric-uid-gid"))]
     numeric_id: bool,
+
+    /// Search in the path index built by `index-paths build` instead of reading the trees. Only
+    /// the paths of the search results are shown
+    #[clap(long)]
+    from_index: bool,
 }
 
 impl Runnable for FindCmd {
@@ -72,6 +89,11 @@ impl FindCmd {
         let config = RUSTIC_APP.config();
         let repo = open_repository_indexed(&config.repository)?;
 
+        let index_dir = self
+            .from_index
+            .then(|| path_index_dir(&repo.config().id.to_hex().to_string()))
+            .transpose()?;
+
         let groups = repo.get_snapshot_group(&self.ids, self.group_by, |sn| {
             config.snapshot_filter.matches(sn)
         })?;
@@ -81,7 +103,9 @@ impl FindCmd {
                 println!("\nsearching in snapshots group {group}...");
             }
             let ids = snapshots.iter().map(|sn| sn.tree);
-            if let Some(path) = &self.path {
+            if let Some(dir) = &index_dir {
+                self.find_in_index(dir, &snapshots)?;
+            } else if let Some(path) = &self.path {
                 let FindNode { nodes, matches } = repo.find_nodes_from_path(ids, path)?;
                 for (idx, g) in &matches
                     .iter()
@@ -94,17 +118,8 @@ impl FindCmd {
                     }
                 }
             } else {
-                let mut builder = GlobSetBuilder::new();
-                for glob in &self.glob {
-                    _ = builder.add(Glob::new(glob)?);
-                }
-                for glob in &self.iglob {
-                    _ = builder.add(GlobBuilder::new(glob).case_insensitive(true).build()?);
-                }
-                let globset = builder.build()?;
-                let matches = |path: &Path, _: &Node| {
-                    globset.is_match(path) || path.file_name().is_some_and(|f| globset.is_match(f))
-                };
+                let path_matches = self.path_matcher()?;
+                let matches = |path: &Path, _: &Node| path_matches(path);
                 let FindMatches {
                     paths,
                     nodes,
@@ -125,6 +140,67 @@ impl FindCmd {
         Ok(())
     }
 
+    /// Get a function checking if a path matches the given globs and regular expressions
+    fn path_matcher(&self) -> Result<impl Fn(&Path) -> bool> {
+        let mut builder = GlobSetBuilder::new();
+        for glob in &self.glob {
+            _ = builder.add(Glob::new(glob)?);
+        }
+        for glob in &self.iglob {
+            _ = builder.add(GlobBuilder::new(glob).case_insensitive(true).build()?);
+        }
+        let globset = builder.build()?;
+        let regexes = RegexSet::new(&self.regex)?;
+        Ok(move |path: &Path| {
+            globset.is_match(path)
+                || path.file_name().is_some_and(|f| globset.is_match(f))
+                || regexes.is_match(&path.to_string_lossy())
+                || path
+                    .file_name()
+                    .is_some_and(|f| regexes.is_match(&f.to_string_lossy()))
+        })
+    }
+
+    /// Search the paths of the given snapshots in the path index and print the results
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory of the path index
+    /// * `snapshots` - The snapshots to search in
+    fn find_in_index(&self, dir: &Path, snapshots: &[SnapshotFile]) -> Result<()> {
+        let path_matches = self.path_matcher()?;
+        let exact_path = self
+            .path
+            .as_ref()
+            .map(|path| path.strip_prefix("/").unwrap_or(path));
+        let mut results = Vec::new();
+        for sn in snapshots {
+            let paths = read_path_index(dir, sn)?.ok_or_else(|| {
+                anyhow!(
+                    "snapshot {} is not in the path index, run `rustic index-paths build` first.",
+                    sn.id
+                )
+            })?;
+            let found: Vec<_> = paths
+                .into_iter()
+                .filter(|path| exact_path.map_or_else(|| path_matches(path), |p| path == p))
+                .collect();
+            results.push(found);
+        }
+
+        for (found, g) in &results
+            .iter()
+            .zip(snapshots.iter())
+            .chunk_by(|(found, _)| *found)
+        {
+            self.print_identical_snapshots(found.iter(), g.into_iter().map(|(_, sn)| sn));
+            for path in found {
+                println!("{}", path.display());
+            }
+        }
+        Ok(())
+    }
+
     fn print_identical_snapshots<'a>(
         &self,
         mut idx: impl Iterator,
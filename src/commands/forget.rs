@@ -1,5 +1,7 @@
 //! `forget` subcommand
 
+use std::{fmt, str::FromStr};
+
 use crate::{
     commands::open_repository, helpers::table_with_titles, status_err, Application, RusticConfig,
     RUSTIC_APP,
@@ -7,17 +9,21 @@ use crate::{
 
 use abscissa_core::{config::Override, Shutdown};
 use abscissa_core::{Command, FrameworkError, Runnable};
-use anyhow::Result;
+use anyhow::{bail, Result};
 
-use chrono::Local;
+use chrono::{DateTime, Duration, Local};
 use merge::Merge;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 
-use crate::{commands::prune::PruneCmd, filtering::SnapshotFilter};
+use crate::{
+    commands::prune::{PruneCmd, PruneSummary},
+    filtering::SnapshotFilter,
+};
 
 use rustic_core::{
-    ForgetGroup, ForgetGroups, ForgetSnapshot, KeepOptions, SnapshotGroup, SnapshotGroupCriterion,
+    repofile::SnapshotId, ForgetGroup, ForgetGroups, ForgetSnapshot, KeepOptions, SnapshotGroup,
+    SnapshotGroupCriterion,
 };
 
 /// `forget` subcommand
@@ -65,7 +71,7 @@ impl Override<RusticConfig> for ForgetCmd {
 /// Forget options
 #[serde_as]
 #[derive(Clone, Default, Debug, clap::Parser, Serialize, Deserialize, Merge)]
-#[serde(default, rename_all = "kebab-case")]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ForgetOptions {
     /// Group snapshots by any combination of host,label,paths,tags (default: "host,label,paths")
     #[clap(long, short = 'g', value_name = "CRITERION")]
@@ -77,6 +83,13 @@ pub struct ForgetOptions {
     #[merge(strategy = merge::bool::overwrite_false)]
     prune: bool,
 
+    /// Keep snapshots of the given retention class (see `backup --retain`) which are younger than
+    /// DURATION; without DURATION, they are always kept (can be specified multiple times)
+    #[clap(long, value_name = "CLASS[=DURATION]")]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[merge(strategy = merge::vec::overwrite_empty)]
+    keep_retain: Vec<RetainRule>,
+
     /// Snapshot filter options
     #[clap(flatten, next_help_heading = "Snapshot filter options")]
     #[serde(flatten)]
@@ -88,6 +101,109 @@ pub struct ForgetOptions {
     keep: KeepOptions,
 }
 
+/// Prefix of the tag which saves the retention class of a snapshot
+pub(crate) const RETAIN_TAG_PREFIX: &str = "retain:";
+
+/// Check that a retention class can be saved as tag and used in a retain rule
+///
+/// # Errors
+///
+/// If the class is empty or contains `,`, which separates tags, or `=`, which separates the
+/// duration of a retain rule
+pub(crate) fn check_retain_class(class: &str) -> Result<()> {
+    if class.is_empty() {
+        bail!("retention class must not be empty.");
+    }
+    if class.contains([',', '=']) {
+        bail!("retention class {class:?} must not contain ',' or '='.");
+    }
+    Ok(())
+}
+
+/// Keep rule for snapshots of a retention class
+#[derive(Clone, Debug)]
+pub struct RetainRule {
+    /// The retention class
+    class: String,
+    /// Keep snapshots younger than this duration; if not set, keep all snapshots
+    within: Option<humantime::Duration>,
+}
+
+impl FromStr for RetainRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (class, within) = match s.split_once('=') {
+            Some((class, within)) => (class, Some(within.parse()?)),
+            None => (s, None),
+        };
+        if class.is_empty() {
+            bail!("invalid retain rule {s:?}, use CLASS[=DURATION]");
+        }
+        check_retain_class(class)?;
+        Ok(Self {
+            class: class.to_string(),
+            within,
+        })
+    }
+}
+
+impl fmt::Display for RetainRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.within {
+            Some(within) => write!(f, "{}={within}", self.class),
+            None => f.write_str(&self.class),
+        }
+    }
+}
+
+/// Keep all snapshots which match one of the retain rules
+///
+/// # Arguments
+///
+/// * `groups` - The forget groups to modify
+/// * `rules` - The retain rules
+/// * `now` - The current time
+fn apply_retain_rules(groups: &mut ForgetGroups, rules: &[RetainRule], now: DateTime<Local>) {
+    for snap in groups.0.iter_mut().flat_map(|group| &mut group.snapshots) {
+        if snap.keep {
+            continue;
+        }
+        let class = snap
+            .snapshot
+            .tags
+            .iter()
+            .find_map(|tag| tag.strip_prefix(RETAIN_TAG_PREFIX));
+        let Some(rule) = class.and_then(|class| rules.iter().find(|rule| rule.class == class))
+        else {
+            continue;
+        };
+        let keep = match rule.within {
+            // durations reaching before the earliest representable time keep all snapshots
+            Some(within) => Duration::from_std(*within)
+                .ok()
+                .and_then(|within| now.checked_sub_signed(within))
+                .map_or(true, |start| snap.snapshot.time > start),
+            None => true,
+        };
+        if keep {
+            snap.keep = true;
+            snap.reasons.push(format!("retain {}", rule.class));
+        }
+    }
+}
+
+/// Combined output of `forget --prune --json`
+#[derive(Serialize)]
+struct ForgetPruneSummary {
+    /// The snapshot groups with keep/remove decisions
+    groups: serde_json::Value,
+    /// The ids of the removed snapshots
+    removed_snapshots: Vec<SnapshotId>,
+    /// The summary of the prune run
+    prune: PruneSummary,
+}
+
 impl Runnable for ForgetCmd {
     fn run(&self) {
         if let Err(err) = self.inner_run() {
@@ -108,9 +224,11 @@ impl ForgetCmd {
         let group_by = config.forget.group_by.unwrap_or_default();
 
         let groups = if self.ids.is_empty() {
-            repo.get_forget_snapshots(&config.forget.keep, group_by, |sn| {
+            let mut groups = repo.get_forget_snapshots(&config.forget.keep, group_by, |sn| {
                 config.forget.filter.matches(sn)
-            })?
+            })?;
+            apply_retain_rules(&mut groups, &config.forget.keep_retain, Local::now());
+            groups
         } else {
             let now = Local::now();
             let item = ForgetGroup {
@@ -138,9 +256,12 @@ impl ForgetCmd {
             ForgetGroups(vec![item])
         };
 
-        if self.json {
+        // with --prune, the groups are printed together with the prune summary
+        let combined_json = self.json && config.forget.prune;
+        let groups_json = serde_json::to_value(&groups)?;
+        if self.json && !combined_json {
             let mut stdout = std::io::stdout();
-            serde_json::to_writer_pretty(&mut stdout, &groups)?;
+            serde_json::to_writer_pretty(&mut stdout, &groups_json)?;
         } else if !self.quiet {
             print_groups(&groups);
         }
@@ -158,7 +279,17 @@ impl ForgetCmd {
             (_, _, true) => {}
         }
 
-        if config.forget.prune {
+        if combined_json {
+            let mut prune_opts = self.prune_opts.clone();
+            prune_opts.opts.ignore_snaps = forget_snaps.clone();
+            let summary = ForgetPruneSummary {
+                groups: groups_json,
+                removed_snapshots: forget_snaps,
+                prune: prune_opts.execute(true)?,
+            };
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &summary)?;
+        } else if config.forget.prune {
             let mut prune_opts = self.prune_opts.clone();
             prune_opts.opts.ignore_snaps = forget_snaps;
             prune_opts.run();
@@ -210,3 +341,66 @@ fn print_groups(groups: &ForgetGroups) {
         println!();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use rustic_core::{repofile::SnapshotFile, StringList};
+
+    #[rstest]
+    #[case("daily", "daily")]
+    #[case("weekly=7d", "weekly=7days")]
+    fn retain_rule_round_trip(#[case] input: &str, #[case] expected: &str) -> Result<()> {
+        let rule = RetainRule::from_str(input)?;
+        assert_eq!(rule.to_string(), expected);
+        assert_eq!(RetainRule::from_str(expected)?.to_string(), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("=7d")]
+    #[case("daily,weekly")]
+    #[case("daily=often")]
+    fn invalid_retain_rule(#[case] input: &str) {
+        assert!(RetainRule::from_str(input).is_err());
+    }
+
+    #[rstest]
+    #[case("daily", "retain:daily", 100, true)]
+    #[case("daily=7d", "retain:daily", 3, true)]
+    #[case("daily=7d", "retain:daily", 10, false)]
+    #[case("daily", "retain:weekly", 3, false)]
+    #[case("daily", "daily", 3, false)]
+    #[case("daily=300000years", "retain:daily", 100, true)]
+    #[case("daily=500000000years", "retain:daily", 100, true)]
+    fn retain_rules_keep_snapshots(
+        #[case] rule: &str,
+        #[case] tag: &str,
+        #[case] age_days: i64,
+        #[case] expected: bool,
+    ) -> Result<()> {
+        let now = Local::now();
+        let snapshot = SnapshotFile {
+            time: now - Duration::days(age_days),
+            tags: StringList::from_str(tag)?,
+            ..Default::default()
+        };
+        let mut groups = ForgetGroups(vec![ForgetGroup {
+            group: SnapshotGroup::default(),
+            snapshots: vec![ForgetSnapshot {
+                snapshot,
+                keep: false,
+                reasons: Vec::new(),
+            }],
+        }]);
+
+        apply_retain_rules(&mut groups, &[RetainRule::from_str(rule)?], now);
+        let snap = &groups.0[0].snapshots[0];
+        assert_eq!(snap.keep, expected);
+        assert_eq!(snap.reasons.is_empty(), !expected);
+        Ok(())
+    }
+}
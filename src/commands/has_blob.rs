@@ -0,0 +1,99 @@
+//! `has-blob` subcommand
+
+use std::io::BufRead;
+
+use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use rustic_core::{repofile::BlobType, BlobId, DataId, Id, TreeId};
+
+/// `has-blob` subcommand
+///
+/// Check if blobs are contained in the index of the repository
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct HasBlobCmd {
+    /// Full ids of the blobs to check
+    #[clap(value_name = "ID", required_unless_present = "stdin")]
+    ids: Vec<String>,
+
+    /// Read the blob ids from stdin, one id per line
+    #[clap(long)]
+    stdin: bool,
+
+    /// Show the result as json, one object per line
+    #[clap(long)]
+    json: bool,
+}
+
+/// The result for a single blob id
+#[derive(Serialize)]
+struct BlobInfo<'a> {
+    id: &'a str,
+    exists: bool,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    tpe: Option<BlobType>,
+}
+
+impl Runnable for HasBlobCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl HasBlobCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository_indexed(&config.repository)?;
+
+        let mut ids = self.ids.clone();
+        if self.stdin {
+            for line in std::io::stdin().lock().lines() {
+                let line = line?;
+                let id = line.trim();
+                if !id.is_empty() {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+        let blob_ids = ids
+            .iter()
+            .map(|id| {
+                let blob_id = Id::from_hex(id)
+                    .ok()
+                    .filter(|_| id.len() == 64)
+                    .ok_or_else(|| anyhow!("{id} is not a full blob id."))?;
+                Ok(BlobId::from(blob_id))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for (id, blob_id) in ids.iter().zip(blob_ids) {
+            let tpe = if repo.get_index_entry(&TreeId::from(*blob_id)).is_ok() {
+                Some(BlobType::Tree)
+            } else if repo.get_index_entry(&DataId::from(*blob_id)).is_ok() {
+                Some(BlobType::Data)
+            } else {
+                None
+            };
+            if self.json {
+                let info = BlobInfo {
+                    id,
+                    exists: tpe.is_some(),
+                    tpe,
+                };
+                println!("{}", serde_json::to_string(&info)?);
+            } else {
+                match tpe {
+                    Some(tpe) => println!("{id} {tpe:?}"),
+                    None => println!("{id} missing"),
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! `index-paths` subcommand
+//!
+//! The path index contains the paths of all nodes of a snapshot. It is saved in the cache, one
+//! file per snapshot, and allows `find --from-index` to search paths without reading any trees.
+//! As snapshots never change, an index file is never outdated; files of removed snapshots are
+//! deleted when building the index.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, Context, Result};
+use log::info;
+
+use rustic_core::{repofile::SnapshotFile, LsOptions, Progress, ProgressBars, RusticResult};
+
+/// Name of the directory within the cache of a repository containing the path index
+pub(crate) const PATH_INDEX_DIR: &str = "paths";
+
+/// `index-paths` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct IndexPathsCmd {
+    #[clap(subcommand)]
+    cmd: IndexPathsSubCmd,
+}
+
+/// `index-paths` subcommands
+#[derive(clap::Subcommand, Debug)]
+enum IndexPathsSubCmd {
+    /// Add missing snapshots to the path index and remove removed snapshots from it
+    Build(BuildOpts),
+}
+
+#[derive(clap::Parser, Debug)]
+struct BuildOpts {
+    /// Snapshots to index. If none is given, use filter options to filter from all snapshots
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+}
+
+impl Runnable for IndexPathsCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl IndexPathsCmd {
+    fn inner_run(&self) -> Result<()> {
+        match &self.cmd {
+            IndexPathsSubCmd::Build(opts) => opts.build(),
+        }
+    }
+}
+
+/// Get the directory of the path index of the repository with the given id
+///
+/// # Arguments
+///
+/// * `repo_id` - The id of the repository
+pub(crate) fn path_index_dir(repo_id: &str) -> Result<PathBuf> {
+    let cache_dir = RUSTIC_APP
+        .config()
+        .repository
+        .cache_dir()
+        .ok_or_else(|| anyhow!("the path index needs a cache directory."))?;
+    Ok(cache_dir.join(repo_id).join(PATH_INDEX_DIR))
+}
+
+/// Read the indexed paths of a snapshot
+///
+/// Returns `None` if the snapshot is not indexed.
+///
+/// # Arguments
+///
+/// * `dir` - The directory of the path index
+/// * `sn` - The snapshot
+pub(crate) fn read_path_index(dir: &Path, sn: &SnapshotFile) -> Result<Option<Vec<PathBuf>>> {
+    let file = dir.join(sn.id.to_hex().to_string());
+    if !file.is_file() {
+        return Ok(None);
+    }
+    let data = fs::read(&file).with_context(|| format!("error reading {file:?}"))?;
+    let paths: Vec<PathBuf> = serde_json::from_slice(&data)?;
+    Ok(Some(paths))
+}
+
+impl BuildOpts {
+    fn build(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository_indexed(&config.repository)?;
+        let dir = path_index_dir(&repo.config().id.to_hex().to_string())?;
+        fs::create_dir_all(&dir)?;
+
+        // remove index files of snapshots which no longer exist
+        let existing: BTreeSet<_> = repo
+            .get_all_snapshots()?
+            .into_iter()
+            .map(|sn| sn.id.to_hex().to_string())
+            .collect();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !existing.contains(&name) {
+                if config.global.dry_run {
+                    info!("would remove index of snapshot {name}");
+                } else {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+
+        let snapshots = if self.ids.is_empty() {
+            repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
+        } else {
+            repo.get_snapshots(&self.ids)?
+        };
+        let missing: Vec<_> = snapshots
+            .into_iter()
+            .filter(|sn| !dir.join(sn.id.to_hex().to_string()).exists())
+            .collect();
+        if config.global.dry_run {
+            info!("would index {} snapshots", missing.len());
+            return Ok(());
+        }
+
+        let p = config
+            .global
+            .progress_options
+            .progress_counter("indexing snapshots...");
+        p.set_length(missing.len().try_into()?);
+        let mut ls_opts = LsOptions::default();
+        ls_opts.recursive = true;
+        for sn in &missing {
+            let node = repo.node_from_snapshot_and_path(sn, "")?;
+            let paths = repo
+                .ls(&node, &ls_opts)?
+                // paths which are no valid unicode can only be searched approximately
+                .map(|item| item.map(|(path, _)| path.to_string_lossy().to_string()))
+                .collect::<RusticResult<Vec<_>>>()?;
+
+            // write to a temporary file first such that no partial index is used
+            let file = dir.join(sn.id.to_hex().to_string());
+            let tmp = file.with_extension("tmp");
+            fs::write(&tmp, serde_json::to_vec(&paths)?)?;
+            fs::rename(&tmp, &file)?;
+            p.inc(1);
+        }
+        p.finish();
+        info!("indexed {} snapshots.", missing.len());
+
+        Ok(())
+    }
+}
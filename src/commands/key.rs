@@ -1,15 +1,22 @@
 //! `key` subcommand
 
-use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
+use crate::{
+    commands::{get_repository_with_backends, open_repository},
+    helpers::table_with_titles,
+    status_err, Application, RUSTIC_APP,
+};
 
 use std::path::PathBuf;
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
+use anyhow::{bail, Result};
 use dialoguer::Password;
-use log::info;
+use log::{info, warn};
 
-use rustic_core::{CommandInput, KeyOptions, RepositoryOptions};
+use rustic_core::{
+    repofile::KeyFile, CommandInput, FileType, Id, KeyOptions, OpenStatus, ReadBackend, Repository,
+    RepositoryBackends, RepositoryOptions, WriteBackend,
+};
 
 /// `key` subcommand
 #[derive(clap::Parser, Command, Debug)]
@@ -23,6 +30,15 @@ pub(super) struct KeyCmd {
 enum KeySubCmd {
     /// Add a new key to the repository
     Add(AddCmd),
+
+    /// Change the password: Add a new key and remove the key used to open the repository
+    Passwd(PasswdCmd),
+
+    /// List all keys of the repository, the keys matching the current password are marked with *
+    List(ListCmd),
+
+    /// Remove keys from the repository
+    Remove(RemoveCmd),
 }
 
 #[derive(clap::Parser, Debug)]
@@ -44,6 +60,27 @@ pub(crate) struct AddCmd {
     pub(crate) key_opts: KeyOptions,
 }
 
+#[derive(clap::Parser, Debug)]
+pub(crate) struct PasswdCmd {
+    /// Options for the new key
+    #[clap(flatten)]
+    new_key: AddCmd,
+
+    /// Don't remove the old key
+    #[clap(long)]
+    keep_old: bool,
+}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct ListCmd {}
+
+#[derive(clap::Parser, Debug)]
+pub(crate) struct RemoveCmd {
+    /// Ids (or unique prefixes) of the keys to remove
+    #[clap(value_name = "ID", required = true)]
+    ids: Vec<String>,
+}
+
 impl Runnable for KeyCmd {
     fn run(&self) {
         self.cmd.run();
@@ -63,7 +100,15 @@ impl AddCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config.repository)?;
+        let pass = self.new_password()?;
+        let id = repo.add_key(&pass, &self.key_opts)?;
+        info!("key {id} successfully added.");
 
+        Ok(())
+    }
+
+    /// Get the new password from the options or ask for it
+    fn new_password(&self) -> Result<String> {
         // create new Repository options which just contain password information
         let pass_opts = RepositoryOptions {
             password: self.new_password.clone(),
@@ -83,10 +128,199 @@ impl AddCmd {
                     .with_confirmation("confirm password", "passwords do not match")
                     .interact()?)
             })?;
+        Ok(pass)
+    }
+}
 
-        let id = repo.add_key(&pass, &self.key_opts)?;
-        info!("key {id} successfully added.");
+impl Runnable for PasswdCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl PasswdCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let backends = config.repository.to_backends()?;
+        let (repo, old_pass) = open_with_current_password(&backends)?;
+
+        // the keys to remove are all keys which can be opened using the old password
+        let be = backends.repository();
+        let mut old_keys = Vec::new();
+        for id in be.list(FileType::Key)? {
+            if key_matches(&be, &id, &old_pass)? {
+                old_keys.push(id);
+            }
+        }
+
+        // first add the new key and verify it, such that there is always a valid key
+        let new_pass = self.new_key.new_password()?;
+        let new_id = repo.add_key(&new_pass, &self.new_key.key_opts)?;
+        if !key_matches(&be, &new_id, &new_pass)? {
+            bail!("verifying new key {new_id} failed, keeping old key.");
+        }
+        _ = get_repository_with_backends(&config.repository, &backends, ())?
+            .open_with_password(&new_pass)?;
+        info!("key {new_id} successfully added.");
+
+        if self.keep_old {
+            return Ok(());
+        }
+        for id in old_keys.into_iter().filter(|id| *id != *new_id) {
+            remove_key(&backends, &id)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Runnable for ListCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl ListCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let backends = config.repository.to_backends()?;
+        let (_, pass) = open_with_current_password(&backends)?;
+
+        let be = backends.repository();
+        let mut table = table_with_titles([" ", "ID", "User", "Host", "Created"]);
+        for id in be.list(FileType::Key)? {
+            let data = be.read_full(FileType::Key, &id)?;
+            let key: KeyFile = serde_json::from_slice(&data)?;
+            let current = if key.key_from_password(&pass).is_ok() {
+                "*"
+            } else {
+                ""
+            };
+            _ = table.add_row([
+                current.to_string(),
+                id.to_hex().to_string(),
+                key.username.unwrap_or_default(),
+                key.hostname.unwrap_or_default(),
+                key.created
+                    .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default(),
+            ]);
+        }
+        println!("{table}");
 
         Ok(())
     }
 }
+
+impl Runnable for RemoveCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl RemoveCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let backends = config.repository.to_backends()?;
+        let (_, pass) = open_with_current_password(&backends)?;
+
+        let be = backends.repository();
+        let keys = be.list(FileType::Key)?;
+        let mut to_remove = Vec::new();
+        for prefix in &self.ids {
+            let prefix = prefix.to_lowercase();
+            let mut matching = keys
+                .iter()
+                .filter(|id| id.to_hex().to_string().starts_with(prefix.as_str()));
+            match (matching.next(), matching.next()) {
+                (Some(id), None) => to_remove.push(*id),
+                (None, _) => bail!("no key found for id {prefix}."),
+                (Some(_), Some(_)) => bail!("id {prefix} is ambiguous."),
+            }
+        }
+
+        for id in &to_remove {
+            if key_matches(&be, id, &pass)? {
+                bail!("cannot remove key {id} which is used to open the repository, use `key passwd` to change the password.");
+            }
+        }
+        if keys.iter().all(|id| to_remove.contains(id)) {
+            bail!("cannot remove all keys of the repository.");
+        }
+
+        if config.global.dry_run {
+            for id in &to_remove {
+                info!("would remove key {id}.");
+            }
+            return Ok(());
+        }
+        for id in &to_remove {
+            remove_key(&backends, id)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Open the repository and return it together with the password used to open it
+///
+/// If no password is given by the options, the password is asked for.
+///
+/// # Arguments
+///
+/// * `backends` - The backends to use
+fn open_with_current_password(
+    backends: &RepositoryBackends,
+) -> Result<(Repository<(), OpenStatus>, String)> {
+    let config = RUSTIC_APP.config();
+    let repo = get_repository_with_backends(&config.repository, backends, ())?;
+
+    let pass = match repo.password()? {
+        Some(pass) => pass,
+        None => Password::new()
+            .with_prompt("enter repository password")
+            .allow_empty_password(true)
+            .interact()?,
+    };
+    let repo = repo.open_with_password(&pass)?;
+    Ok((repo, pass))
+}
+
+/// Remove the key file with the given id from the repository (and the hot repository)
+///
+/// # Arguments
+///
+/// * `backends` - The backends to remove the key file from
+/// * `id` - The id of the key file
+fn remove_key(backends: &RepositoryBackends, id: &Id) -> Result<()> {
+    backends.repository().remove(FileType::Key, id, false)?;
+    if let Some(be_hot) = backends.repo_hot() {
+        if let Err(err) = be_hot.remove(FileType::Key, id, false) {
+            warn!("error removing key {id} from hot repository: {err}");
+        }
+    }
+    info!("key {id} successfully removed.");
+    Ok(())
+}
+
+/// Check if the key file with the given id can be opened using the given password
+///
+/// # Arguments
+///
+/// * `be` - The backend to read the key file from
+/// * `id` - The id of the key file
+/// * `pass` - The password to try
+fn key_matches(be: &impl ReadBackend, id: &Id, pass: &str) -> Result<bool> {
+    let data = be.read_full(FileType::Key, id)?;
+    let key: KeyFile = serde_json::from_slice(&data)?;
+    Ok(key.key_from_password(&pass).is_ok())
+}
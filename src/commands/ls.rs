@@ -6,6 +6,7 @@ use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_A
 
 use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::Result;
+use serde::Serialize;
 
 use rustic_core::{
     repofile::{Node, NodeType},
@@ -36,17 +37,21 @@ pub(crate) struct LsCmd {
     snap: String,
 
     /// show summary
-    #[clap(long, short = 's', conflicts_with = "json")]
+    #[clap(long, short = 's', conflicts_with_all = ["json", "ndjson"])]
     summary: bool,
 
     /// show long listing
-    #[clap(long, short = 'l', conflicts_with = "json")]
+    #[clap(long, short = 'l', conflicts_with_all = ["json", "ndjson"])]
     long: bool,
 
     /// show listing in json
-    #[clap(long, conflicts_with_all = ["summary", "long"])]
+    #[clap(long, conflicts_with_all = ["summary", "long", "ndjson"])]
     json: bool,
 
+    /// show full node records (metadata, xattrs, subtree and content ids) as json, one per line
+    #[clap(long, conflicts_with_all = ["summary", "long", "json"])]
+    ndjson: bool,
+
     /// show uid/gid instead of user/group
     #[clap(long, long("numeric-uid-gid"))]
     numeric_id: bool,
@@ -65,6 +70,14 @@ impl Runnable for LsCmd {
     }
 }
 
+/// A node record as printed by `ls --ndjson`
+#[derive(Serialize)]
+struct NodeRecord<'a> {
+    path: &'a Path,
+    #[serde(flatten)]
+    node: &'a Node,
+}
+
 /// Sumary of a ls command
 ///
 /// This struct is used to print a summary of the ls command.
@@ -152,6 +165,12 @@ impl LsCmd {
                     print!(",");
                 }
                 print!("{}", serde_json::to_string(&path)?);
+            } else if self.ndjson {
+                let record = NodeRecord {
+                    path: &path,
+                    node: &node,
+                };
+                println!("{}", serde_json::to_string(&record)?);
             } else if self.long {
                 print_node(&node, &path, self.numeric_id);
             } else {
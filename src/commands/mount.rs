@@ -0,0 +1,103 @@
+//! `mount` subcommand
+
+// ignore markdown clippy lints as we use doc-comments to generate clap help texts
+#![allow(clippy::doc_markdown)]
+
+mod fusefs;
+
+use std::{ffi::OsStr, path::PathBuf, str::FromStr};
+
+use crate::{commands::open_repository_indexed, status_err, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use fuse_mt::{mount, FuseMT};
+use log::info;
+
+use rustic_core::vfs::{FilePolicy, IdenticalSnapshot, Latest, Vfs};
+
+use fusefs::FuseFS;
+
+/// `mount` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct MountCmd {
+    /// The path template to use for snapshots. {id}, {id_long}, {time}, {username}, {hostname}, {label}, {tags}, {backup_start}, {backup_end} are replaced. Use e.g. "{hostname}/{time}" to get a "latest" link for each host.
+    #[clap(long, default_value = "[{hostname}]/[{label}]/{time}")]
+    path_template: String,
+
+    /// The time template to use to display times in the path template. See https://docs.rs/chrono/latest/chrono/format/strftime/index.html for format options.
+    #[clap(long, default_value = "%Y-%m-%d_%H-%M-%S")]
+    time_template: String,
+
+    /// Don't allow other users to access the mount point
+    #[clap(long)]
+    exclusive: bool,
+
+    /// How to handle access to files. [default: "forbidden" for hot/cold repositories, else "read"]
+    #[clap(long)]
+    file_access: Option<String>,
+
+    /// The mount point to use
+    #[clap(value_name = "PATH")]
+    mount_point: PathBuf,
+
+    /// Specify directly which snapshot/path to mount
+    #[clap(value_name = "SNAPSHOT[:PATH]")]
+    snapshot_path: Option<String>,
+}
+
+impl Runnable for MountCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl MountCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository_indexed(&config.repository)?;
+
+        let sn_filter = |sn: &_| config.snapshot_filter.matches(sn);
+
+        let vfs = if let Some(snap) = &self.snapshot_path {
+            let node = repo.node_from_snapshot_path(snap, sn_filter)?;
+            Vfs::from_dir_node(&node)
+        } else {
+            let snapshots = repo.get_matching_snapshots(sn_filter)?;
+            Vfs::from_snapshots(
+                snapshots,
+                &self.path_template,
+                &self.time_template,
+                Latest::AsLink,
+                IdenticalSnapshot::AsLink,
+            )?
+        };
+
+        let file_access = self.file_access.as_ref().map_or_else(
+            || {
+                if repo.config().is_hot == Some(true) {
+                    Ok(FilePolicy::Forbidden)
+                } else {
+                    Ok(FilePolicy::Read)
+                }
+            },
+            |s| FilePolicy::from_str(s),
+        )?;
+
+        let mut options = vec!["ro", "default_permissions", "fsname=rustic"];
+        if !self.exclusive {
+            options.push("allow_other");
+        }
+        let options = ["-o".to_string(), options.join(",")];
+        let options: Vec<_> = options.iter().map(OsStr::new).collect();
+
+        let fs = FuseMT::new(FuseFS::new(repo, vfs, file_access), 1);
+        info!("mounting at {}...", self.mount_point.display());
+        mount(fs, &self.mount_point, &options)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! Read-only FUSE filesystem giving access to the snapshots of a repository
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use fuse_mt::{
+    CallbackResult, DirectoryEntry, FileAttr, FileType, FilesystemMT, RequestInfo, ResultData,
+    ResultEmpty, ResultEntry, ResultOpen, ResultReaddir, ResultSlice,
+};
+
+use rustic_core::{
+    repofile::{Node, NodeType},
+    vfs::{FilePolicy, OpenFile, Vfs},
+    IndexedFull, Repository,
+};
+
+/// Time for which the kernel may cache attributes; the filesystem never changes
+const TTL: Duration = Duration::from_secs(60);
+
+/// A read-only FUSE filesystem serving a [`Vfs`]
+pub(crate) struct FuseFS<P, S> {
+    /// The repository
+    repo: Repository<P, S>,
+    /// The virtual filesystem to serve
+    vfs: Vfs,
+    /// The currently opened files
+    open_files: RwLock<BTreeMap<u64, OpenFile>>,
+    /// The next file handle to use
+    next_fh: AtomicU64,
+    /// The time of mounting, used for missing times of nodes
+    now: SystemTime,
+    /// How to handle access to files
+    file_policy: FilePolicy,
+}
+
+impl<P, S: IndexedFull> FuseFS<P, S> {
+    pub(crate) fn new(repo: Repository<P, S>, vfs: Vfs, file_policy: FilePolicy) -> Self {
+        Self {
+            repo,
+            vfs,
+            open_files: RwLock::default(),
+            next_fh: AtomicU64::new(0),
+            now: SystemTime::now(),
+            file_policy,
+        }
+    }
+
+    fn node_from_path(&self, path: &Path) -> Result<Node, libc::c_int> {
+        self.vfs
+            .node_from_path(&self.repo, path)
+            .map_err(|_| libc::ENOENT)
+    }
+}
+
+/// Get the FUSE file type of a node
+fn node_to_filetype(node: &Node) -> FileType {
+    match node.node_type {
+        NodeType::File => FileType::RegularFile,
+        NodeType::Dir => FileType::Directory,
+        NodeType::Symlink { .. } => FileType::Symlink,
+        NodeType::Chardev { .. } => FileType::CharDevice,
+        NodeType::Dev { .. } => FileType::BlockDevice,
+        NodeType::Fifo => FileType::NamedPipe,
+        NodeType::Socket => FileType::Socket,
+    }
+}
+
+/// Get the FUSE file attributes of a node
+fn node_to_file_attr(node: &Node, now: SystemTime) -> FileAttr {
+    let meta = &node.meta;
+    let time = |t: Option<_>| t.map_or(now, SystemTime::from);
+    let rdev = match node.node_type {
+        NodeType::Dev { device } | NodeType::Chardev { device } => device,
+        _ => 0,
+    };
+    FileAttr {
+        size: meta.size,
+        blocks: meta.size.div_ceil(512),
+        atime: time(meta.atime),
+        mtime: time(meta.mtime),
+        ctime: time(meta.ctime),
+        crtime: time(meta.mtime),
+        kind: node_to_filetype(node),
+        perm: meta
+            .mode
+            .map_or(0o755, |mode| u16::try_from(mode & 0o7777).unwrap_or(0o755)),
+        nlink: u32::try_from(meta.links).unwrap_or(1).max(1),
+        uid: meta.uid.unwrap_or(0),
+        gid: meta.gid.unwrap_or(0),
+        rdev: u32::try_from(rdev).unwrap_or(0),
+        flags: 0,
+    }
+}
+
+impl<P: Send + Sync + 'static, S: IndexedFull + Send + Sync + 'static> FilesystemMT
+    for FuseFS<P, S>
+{
+    fn getattr(&self, _req: RequestInfo, path: &Path, _fh: Option<u64>) -> ResultEntry {
+        let node = self.node_from_path(path)?;
+        Ok((TTL, node_to_file_attr(&node, self.now)))
+    }
+
+    fn readlink(&self, _req: RequestInfo, path: &Path) -> ResultData {
+        let node = self.node_from_path(path)?;
+        if !node.is_symlink() {
+            return Err(libc::EINVAL);
+        }
+        Ok(node.node_type.to_link().as_os_str().as_bytes().to_vec())
+    }
+
+    fn open(&self, _req: RequestInfo, path: &Path, _flags: u32) -> ResultOpen {
+        if matches!(self.file_policy, FilePolicy::Forbidden) {
+            return Err(libc::ENOTSUP);
+        }
+        let node = self.node_from_path(path)?;
+        let open_file = self.repo.open_file(&node).map_err(|_| libc::EIO)?;
+        let fh = self.next_fh.fetch_add(1, Ordering::Relaxed);
+        _ = self.open_files.write().unwrap().insert(fh, open_file);
+        Ok((fh, 0))
+    }
+
+    fn read(
+        &self,
+        _req: RequestInfo,
+        _path: &Path,
+        fh: u64,
+        offset: u64,
+        size: u32,
+        callback: impl FnOnce(ResultSlice<'_>) -> CallbackResult,
+    ) -> CallbackResult {
+        let open_files = self.open_files.read().unwrap();
+        let Some(open_file) = open_files.get(&fh) else {
+            return callback(Err(libc::EBADF));
+        };
+        let (Ok(offset), Ok(size)) = (usize::try_from(offset), usize::try_from(size)) else {
+            return callback(Err(libc::EINVAL));
+        };
+        match self.repo.read_file_at(open_file, offset, size) {
+            Ok(data) => callback(Ok(&data)),
+            Err(_) => callback(Err(libc::EIO)),
+        }
+    }
+
+    fn release(
+        &self,
+        _req: RequestInfo,
+        _path: &Path,
+        fh: u64,
+        _flags: u32,
+        _lock_owner: u64,
+        _flush: bool,
+    ) -> ResultEmpty {
+        _ = self.open_files.write().unwrap().remove(&fh);
+        Ok(())
+    }
+
+    fn opendir(&self, _req: RequestInfo, _path: &Path, _flags: u32) -> ResultOpen {
+        Ok((0, 0))
+    }
+
+    fn readdir(&self, _req: RequestInfo, path: &Path, _fh: u64) -> ResultReaddir {
+        let nodes = self
+            .vfs
+            .dir_entries_from_path(&self.repo, path)
+            .map_err(|_| libc::ENOENT)?;
+        let dots = [".", ".."].into_iter().map(|name| DirectoryEntry {
+            name: OsString::from(name),
+            kind: FileType::Directory,
+        });
+        Ok(dots
+            .chain(nodes.iter().map(|node| DirectoryEntry {
+                name: node.name(),
+                kind: node_to_filetype(node),
+            }))
+            .collect())
+    }
+
+    fn releasedir(&self, _req: RequestInfo, _path: &Path, _fh: u64, _flags: u32) -> ResultEmpty {
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+//! `prove` and `verify-proof` subcommands
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    commands::{open_repository_indexed, open_repository_with_backends},
+    status_err, Application, RUSTIC_APP,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueHint;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
+use sha2::{Digest, Sha256};
+
+use rustic_core::{
+    repofile::{BlobType, FileType, Node, Tree},
+    Id, ReadBackend,
+};
+
+/// `prove` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct ProveCmd {
+    /// Snapshot/path to prove
+    #[clap(value_name = "SNAPSHOT[:PATH]")]
+    snap: String,
+}
+
+/// `verify-proof` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct VerifyProofCmd {
+    /// Full id of the snapshot the proof must belong to
+    #[clap(value_name = "SNAPSHOT_ID")]
+    id: String,
+
+    /// File containing the proof generated by `prove`. If a repository is given, the root tree is
+    /// verified using the repository key, otherwise it remains unverified.
+    #[clap(value_name = "PROOF", value_hint = ValueHint::FilePath)]
+    proof: PathBuf,
+}
+
+/// A proof that a path is contained in a snapshot
+///
+/// The snapshot file is stored encrypted, so its hash is the snapshot id. The trees are stored
+/// decrypted, so the hash of each tree is its tree id. Together, they form the chain from the
+/// snapshot down to the node of the path.
+///
+/// Note that checking that the root tree is the tree of the (encrypted) snapshot file needs the
+/// repository key. `verify-proof` therefore only checks the root tree if a repository is given.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct Proof {
+    /// Id of the snapshot
+    snapshot: String,
+    /// Raw (encrypted) snapshot file
+    #[serde_as(as = "Base64")]
+    snapshot_file: Vec<u8>,
+    /// Id of the root tree of the snapshot
+    root: String,
+    /// Path within the snapshot
+    path: String,
+    /// Trees from the root tree down to the tree containing the path
+    trees: Vec<ProofTree>,
+}
+
+/// A tree blob within a [`Proof`]
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct ProofTree {
+    /// Id of the tree
+    id: String,
+    /// Raw (decrypted) tree blob
+    #[serde_as(as = "Base64")]
+    data: Vec<u8>,
+}
+
+impl Runnable for ProveCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+impl Runnable for VerifyProofCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Get the hex representation of the sha256 hash of the given data
+fn hash_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Find the node with the given name in a tree
+fn find_node(tree: Tree, name: &Path) -> Option<Node> {
+    tree.nodes
+        .into_iter()
+        .find(|node| node.name().as_os_str() == name.as_os_str())
+}
+
+/// Get the (non-empty) components of a path within a snapshot
+fn path_components(path: &str) -> Result<Vec<&Path>> {
+    Path::new(path)
+        .components()
+        .filter_map(|comp| match comp {
+            Component::Normal(name) => Some(Ok(Path::new(name))),
+            Component::RootDir | Component::CurDir => None,
+            _ => Some(Err(anyhow!("invalid path {path:?}"))),
+        })
+        .collect()
+}
+
+impl ProveCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository_indexed(&config.repository)?;
+
+        let (id, path) = self.snap.split_once(':').unwrap_or((&self.snap, ""));
+        let snap = repo.get_snapshot_from_str(id, |sn| config.snapshot_filter.matches(sn))?;
+        let snapshot_file = config
+            .repository
+            .to_backends()?
+            .repository()
+            .read_full(FileType::Snapshot, &snap.id.into())?
+            .to_vec();
+
+        let components = path_components(path)?;
+        let mut trees = Vec::new();
+        let mut tree_id = snap.tree;
+        for (i, name) in components.iter().enumerate() {
+            let id = tree_id.to_hex().to_string();
+            let data = repo.cat_blob(BlobType::Tree, &id)?.to_vec();
+            let tree: Tree = serde_json::from_slice(&data)?;
+            trees.push(ProofTree { id, data });
+
+            let node = find_node(tree, name)
+                .ok_or_else(|| anyhow!("{name:?} not found in snapshot {}", snap.id))?;
+            if i + 1 < components.len() {
+                tree_id = node
+                    .subtree
+                    .ok_or_else(|| anyhow!("{name:?} is not a directory"))?;
+            }
+        }
+        if components.is_empty() {
+            let id = tree_id.to_hex().to_string();
+            let data = repo.cat_blob(BlobType::Tree, &id)?.to_vec();
+            trees.push(ProofTree { id, data });
+        }
+
+        let proof = Proof {
+            snapshot: snap.id.to_hex().to_string(),
+            snapshot_file,
+            root: snap.tree.to_hex().to_string(),
+            path: path.to_string(),
+            trees,
+        };
+        let mut stdout = std::io::stdout();
+        serde_json::to_writer_pretty(&mut stdout, &proof)?;
+        Ok(())
+    }
+}
+
+/// Check that the root tree of the proof is the tree of its snapshot file
+///
+/// This decrypts the snapshot file using the key of the configured repository.
+///
+/// # Arguments
+///
+/// * `proof` - The proof to check, the snapshot file must already match the snapshot id
+fn verify_root(proof: &Proof) -> Result<()> {
+    let config = RUSTIC_APP.config();
+    let backends = config.repository.to_backends()?;
+    let repo = open_repository_with_backends(&config.repository, &backends)?;
+
+    // the snapshot file of the repository is read and decrypted, so make sure it is the same
+    let data = backends
+        .repository()
+        .read_full(FileType::Snapshot, &Id::from_hex(&proof.snapshot)?)?;
+    if data.as_ref() != proof.snapshot_file.as_slice() {
+        bail!(
+            "snapshot file differs from snapshot {} in the repository",
+            proof.snapshot
+        );
+    }
+    let snap = repo
+        .get_snapshots(&[&proof.snapshot])?
+        .pop()
+        .ok_or_else(|| anyhow!("snapshot {} not found", proof.snapshot))?;
+    if snap.tree.to_hex().to_string() != proof.root {
+        bail!(
+            "root tree {} is not the tree {} of snapshot {}",
+            proof.root,
+            snap.tree,
+            proof.snapshot
+        );
+    }
+    Ok(())
+}
+
+impl VerifyProofCmd {
+    fn inner_run(&self) -> Result<()> {
+        let file = std::fs::File::open(&self.proof)
+            .with_context(|| format!("error opening proof {:?}", self.proof))?;
+        let proof: Proof = serde_json::from_reader(file)?;
+
+        if proof.snapshot != self.id.to_lowercase() {
+            bail!("proof is for snapshot {}", proof.snapshot);
+        }
+        if hash_hex(&proof.snapshot_file) != proof.snapshot {
+            bail!(
+                "snapshot file does not match snapshot id {}",
+                proof.snapshot
+            );
+        }
+
+        let components = path_components(&proof.path)?;
+        if proof.trees.len() != components.len().max(1) {
+            bail!(
+                "proof contains {} trees, but path {:?} needs {}",
+                proof.trees.len(),
+                proof.path,
+                components.len().max(1)
+            );
+        }
+        if proof.trees[0].id != proof.root {
+            bail!("first tree is not the root tree {}", proof.root);
+        }
+
+        let mut node = None;
+        for (i, tree) in proof.trees.iter().enumerate() {
+            if hash_hex(&tree.data) != tree.id {
+                bail!("tree {} does not match its id", tree.id);
+            }
+            let Some(name) = components.get(i) else {
+                break;
+            };
+            let parsed: Tree = serde_json::from_slice(&tree.data)?;
+            let found = find_node(parsed, name)
+                .ok_or_else(|| anyhow!("{name:?} not found in tree {}", tree.id))?;
+            if let Some(next) = proof.trees.get(i + 1) {
+                let subtree = found.subtree.map(|id| id.to_hex().to_string());
+                if subtree.as_deref() != Some(next.id.as_str()) {
+                    bail!(
+                        "{name:?} in tree {} does not point to tree {}",
+                        tree.id,
+                        next.id
+                    );
+                }
+            }
+            node = Some(found);
+        }
+
+        if RUSTIC_APP.config().repository.be.repository.is_some() {
+            verify_root(&proof)?;
+            println!("proof is valid.");
+            println!("snapshot:  {}", proof.snapshot);
+            println!("root tree: {}", proof.root);
+        } else {
+            warn!(
+                "no repository given, the root tree can't be verified without the repository key."
+            );
+            println!("proof is valid up to the root tree, the root tree is UNVERIFIED.");
+            println!("snapshot:  {}", proof.snapshot);
+            println!(
+                "root tree: {} (unverified, compare with `rustic cat snapshot`)",
+                proof.root
+            );
+        }
+        println!("path:      {}", proof.path);
+        if let Some(node) = node {
+            println!("type:      {:?}", node.node_type);
+            println!("size:      {}", node.meta.size);
+            if let Some(content) = node.content {
+                println!("blobs:     {}", content.len());
+            }
+        }
+        Ok(())
+    }
+}
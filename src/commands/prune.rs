@@ -1,12 +1,17 @@
 //! `prune` subcommand
 
+use std::time::Instant;
+
 use crate::{
-    commands::open_repository, helpers::bytes_size_to_string, status_err, Application, RUSTIC_APP,
+    commands::open_repository,
+    helpers::{bytes_size_to_string, log_resource_usage, ResourceUsage},
+    status_err, Application, RUSTIC_APP,
 };
 use abscissa_core::{Command, Runnable, Shutdown};
 use log::debug;
 
 use anyhow::Result;
+use serde::Serialize;
 
 use rustic_core::{PruneOptions, PruneStats};
 
@@ -21,21 +26,76 @@ pub(crate) struct PruneCmd {
 
 impl Runnable for PruneCmd {
     fn run(&self) {
+        let start = Instant::now();
         if let Err(err) = self.inner_run() {
             status_err!("{}", err);
             RUSTIC_APP.shutdown(Shutdown::Crash);
         };
+        log_resource_usage(start);
+    }
+}
+
+/// Summary of a prune run, used for machine-readable output
+#[derive(Debug, Serialize)]
+pub(crate) struct PruneSummary {
+    /// Whether the prune was only planned, but not executed
+    dry_run: bool,
+    /// Number of packs which are deleted
+    packs_deleted: u64,
+    /// Number of packs which are repacked
+    packs_repacked: u64,
+    /// Number of blobs which are removed
+    blobs_removed: u64,
+    /// Number of bytes which are freed
+    bytes_freed: u64,
+    /// Number of bytes remaining in the repository
+    bytes_remaining: u64,
+    /// Resources used by the prune run
+    resources: ResourceUsage,
+}
+
+impl PruneSummary {
+    fn new(stats: &PruneStats, dry_run: bool, resources: ResourceUsage) -> Self {
+        let blob_stat = stats.blobs_sum();
+        let size_stat = stats.size_sum();
+        Self {
+            dry_run,
+            packs_deleted: stats.packs.unused + stats.packs_unref,
+            packs_repacked: stats.packs.repack,
+            blobs_removed: blob_stat.repackrm + blob_stat.remove,
+            bytes_freed: size_stat.repackrm + size_stat.remove + stats.size_unref,
+            bytes_remaining: size_stat.total_after_prune(),
+            resources,
+        }
     }
 }
 
 impl PruneCmd {
     fn inner_run(&self) -> Result<()> {
+        _ = self.execute(false)?;
+        Ok(())
+    }
+
+    /// Run prune and return a summary
+    ///
+    /// # Arguments
+    ///
+    /// * `quiet` - Don't print the statistics
+    pub(crate) fn execute(&self, quiet: bool) -> Result<PruneSummary> {
+        let start = Instant::now();
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config.repository)?;
 
         let pruner = repo.prune_plan(&self.opts)?;
 
-        print_stats(&pruner.stats);
+        if !quiet {
+            print_stats(&pruner.stats);
+        }
+        let mut summary = PruneSummary::new(
+            &pruner.stats,
+            config.global.dry_run,
+            ResourceUsage::since(start),
+        );
 
         if config.global.dry_run {
             repo.warm_up(pruner.repack_packs().into_iter())?;
@@ -43,7 +103,8 @@ impl PruneCmd {
             pruner.do_prune(&repo, &self.opts)?;
         }
 
-        Ok(())
+        summary.resources = ResourceUsage::since(start);
+        Ok(summary)
     }
 }
 
@@ -1,15 +1,29 @@
 //! `restore` subcommand
 
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
 use crate::{
-    commands::open_repository_indexed, helpers::bytes_size_to_string, status_err, Application,
-    RUSTIC_APP,
+    backend::{
+        datacache::{with_data_cache, DataCacheDir, DATA_RANGES_DIR},
+        throttle::throttled,
+    },
+    commands::open_repository_with_backends,
+    helpers::{bytes_size_to_string, table_right_from},
+    status_err, Application, RUSTIC_APP,
 };
 
 use abscissa_core::{Command, Runnable, Shutdown};
-use anyhow::Result;
-use log::info;
+use anyhow::{bail, Result};
+use bytesize::ByteSize;
+use log::{debug, info};
 
-use rustic_core::{LocalDestination, LsOptions, RestoreOptions};
+use rustic_core::{
+    repofile::Node, IndexedFull, LocalDestination, LsOptions, PackId, Repository, RestoreOptions,
+    RusticResult,
+};
 
 use crate::filtering::SnapshotFilter;
 
@@ -39,6 +53,52 @@ pub(crate) struct RestoreCmd {
         next_help_heading = "Snapshot filter options (when using latest)"
     )]
     filter: SnapshotFilter,
+
+    /// Only show how many packs, ranged reads and bytes need to be fetched, don't restore
+    #[clap(long)]
+    plan: bool,
+
+    /// Limit the download bandwidth of restore per second, e.g. "10MiB". 0 means no limit
+    #[clap(long, value_name = "SIZE")]
+    limit_download: Option<ByteSize>,
+
+    /// Wait time before reading from the next pack
+    #[clap(long, value_name = "DURATION")]
+    pause_between_packs: Option<humantime::Duration>,
+
+    /// Cache the data read from data packs in the cache directory. This speeds up repeated
+    /// restores of the same data, but may need a lot of disk space.
+    #[clap(long)]
+    cache_data: bool,
+
+    /// Maximum total size of the data cached by `--cache-data`, least recently used data is
+    /// removed first
+    #[clap(long, value_name = "SIZE", default_value = "4GiB")]
+    cache_data_max_size: ByteSize,
+
+    /// How to handle files which already exist at the destination
+    #[clap(long, value_name = "POLICY", value_enum, default_value_t)]
+    existing: ExistingPolicy,
+
+    /// Rename existing files by appending SUFFIX before restoring them, implies `--existing rename`.
+    /// If the renamed file also exists, a number is appended as well [default: ".orig"]
+    #[clap(long, value_name = "SUFFIX")]
+    backup_existing: Option<String>,
+}
+
+/// How to handle files which already exist at the destination
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+enum ExistingPolicy {
+    /// Overwrite existing files, keeping contents which already match
+    #[default]
+    Overwrite,
+    /// Don't restore files which already exist, cannot be used with `--delete`
+    Skip,
+    /// Abort if any file already exists
+    Fail,
+    /// Rename existing files (see `--backup-existing`) and restore the files, cannot be used with
+    /// `--delete`
+    Rename,
 }
 impl Runnable for RestoreCmd {
     fn run(&self) {
@@ -52,8 +112,32 @@ impl Runnable for RestoreCmd {
 impl RestoreCmd {
     fn inner_run(&self) -> Result<()> {
         let config = RUSTIC_APP.config();
-        let dry_run = config.global.dry_run;
-        let repo = open_repository_indexed(&config.repository)?;
+        let dry_run = config.global.dry_run || self.plan;
+        let mut backends = throttled(
+            config.repository.to_backends()?,
+            self.limit_download.map(|limit| limit.as_u64()),
+            self.pause_between_packs.map(Into::into),
+        );
+        let data_cache_dir = DataCacheDir::default();
+        let cache_dir = config.repository.cache_dir();
+        if self.cache_data {
+            if cache_dir.is_none() {
+                bail!("--cache-data cannot be used without cache.");
+            }
+            backends =
+                with_data_cache(backends, &data_cache_dir, self.cache_data_max_size.as_u64());
+        }
+        let repo = open_repository_with_backends(&config.repository, &backends)?;
+        if let Some(cache_dir) = cache_dir {
+            // the cached ranges are saved per repository
+            let repo_id = repo.config().id.to_hex().to_string();
+            _ = data_cache_dir.set(cache_dir.join(repo_id).join(DATA_RANGES_DIR));
+        }
+        let repo = if config.global.check_index {
+            repo.to_indexed_checked()
+        } else {
+            repo.to_indexed()
+        }?;
 
         let node =
             repo.node_from_snapshot_path(&self.snap, |sn| config.snapshot_filter.matches(sn))?;
@@ -61,7 +145,12 @@ impl RestoreCmd {
         // for restore, always recurse into tree
         let mut ls_opts = self.ls_opts.clone();
         ls_opts.recursive = true;
-        let ls = repo.ls(&node, &ls_opts)?;
+        let skipped = self.handle_existing(&repo, &node, &ls_opts, dry_run)?;
+        let not_skipped = |item: &RusticResult<(PathBuf, Node)>| {
+            item.as_ref()
+                .map_or(true, |(path, _)| !skipped.contains(path))
+        };
+        let ls = repo.ls(&node, &ls_opts)?.filter(not_skipped);
 
         let dest = LocalDestination::new(&self.dest, true, !node.is_dir())?;
 
@@ -92,17 +181,320 @@ impl RestoreCmd {
             info!("all file contents are fine.");
         }
 
-        if dry_run {
+        if self.plan {
+            let ls = repo.ls(&node, &ls_opts)?.filter(not_skipped);
+            print_plan(&repo, restore_infos.to_packs(), ls)?;
+        } else if dry_run {
             repo.warm_up(restore_infos.to_packs().into_iter())?;
         } else {
             // save some memory
             let repo = repo.drop_data_from_index();
 
-            let ls = repo.ls(&node, &ls_opts)?;
+            let ls = repo.ls(&node, &ls_opts)?.filter(not_skipped);
             repo.restore(restore_infos, &self.opts, ls, &dest)?;
             println!("restore done.");
         }
 
         Ok(())
     }
+
+    /// Handle files which already exist at the destination according to the policy
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository
+    /// * `node` - The node to restore
+    /// * `ls_opts` - The options to list the nodes to restore
+    /// * `dry_run` - Only show which files would be renamed
+    ///
+    /// # Returns
+    ///
+    /// The paths (relative to `node`) of the files which must not be restored
+    fn handle_existing<P, S: IndexedFull>(
+        &self,
+        repo: &Repository<P, S>,
+        node: &Node,
+        ls_opts: &LsOptions,
+        dry_run: bool,
+    ) -> Result<BTreeSet<PathBuf>> {
+        let policy = self.existing_policy()?;
+        if policy == ExistingPolicy::Overwrite {
+            return Ok(BTreeSet::new());
+        }
+
+        let mut existing = Vec::new();
+        for item in repo.ls(node, ls_opts)? {
+            let (path, file) = item?;
+            if file.is_dir() {
+                continue;
+            }
+            // a single file is restored directly to the destination
+            let dest = if node.is_dir() {
+                Path::new(&self.dest).join(&path)
+            } else {
+                PathBuf::from(&self.dest)
+            };
+            if dest.symlink_metadata().is_ok() {
+                existing.push((path, dest));
+            }
+        }
+        self.apply_policy(policy, existing, dry_run)
+    }
+
+    /// Get the policy for files which already exist at the destination
+    ///
+    /// # Errors
+    ///
+    /// If the policy cannot be used together with `--delete`
+    fn existing_policy(&self) -> Result<ExistingPolicy> {
+        let policy = match (self.existing, &self.backup_existing) {
+            (ExistingPolicy::Overwrite, Some(_)) => ExistingPolicy::Rename,
+            (policy, _) => policy,
+        };
+        // --delete would remove skipped files and renamed files as they are not in the snapshot
+        match policy {
+            ExistingPolicy::Rename if self.opts.delete => {
+                bail!("renaming existing files cannot be used together with --delete.");
+            }
+            ExistingPolicy::Skip if self.opts.delete => {
+                bail!("skipping existing files cannot be used together with --delete.");
+            }
+            _ => Ok(policy),
+        }
+    }
+
+    /// Apply the policy to the files which already exist at the destination
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The policy
+    /// * `existing` - The paths (relative to the restored node) and destinations of the existing files
+    /// * `dry_run` - Only show which files would be renamed
+    ///
+    /// # Returns
+    ///
+    /// The paths (relative to the restored node) of the files which must not be restored
+    fn apply_policy(
+        &self,
+        policy: ExistingPolicy,
+        existing: Vec<(PathBuf, PathBuf)>,
+        dry_run: bool,
+    ) -> Result<BTreeSet<PathBuf>> {
+        match policy {
+            ExistingPolicy::Overwrite => {}
+            ExistingPolicy::Fail if !existing.is_empty() => {
+                bail!(
+                    "{} files already exist at the destination, e.g. {:?}.",
+                    existing.len(),
+                    existing[0].1
+                );
+            }
+            ExistingPolicy::Fail => {}
+            ExistingPolicy::Skip => {
+                info!("skipping {} existing files.", existing.len());
+                return Ok(existing.into_iter().map(|(path, _)| path).collect());
+            }
+            ExistingPolicy::Rename => {
+                let suffix = self.backup_existing.as_deref().unwrap_or(".orig");
+                for (_, dest) in existing {
+                    let backup = backup_path(&dest, suffix);
+                    if dry_run {
+                        info!("would rename {dest:?} to {backup:?}");
+                    } else {
+                        debug!("renaming {dest:?} to {backup:?}");
+                        std::fs::rename(&dest, &backup)?;
+                    }
+                }
+            }
+        }
+        Ok(BTreeSet::new())
+    }
+}
+
+/// Get the path to rename an existing file to
+///
+/// This is the path with `suffix` appended. If this path already exists, a number is appended as
+/// well, such that no file is overwritten.
+///
+/// # Arguments
+///
+/// * `path` - The path of the existing file
+/// * `suffix` - The suffix to append
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(suffix);
+    let mut candidate = PathBuf::from(&backup);
+    let mut number = 1;
+    while candidate.symlink_metadata().is_ok() {
+        let mut numbered = backup.clone();
+        numbered.push(format!(".{number}"));
+        candidate = PathBuf::from(numbered);
+        number += 1;
+    }
+    candidate
+}
+
+/// Print how many packs, ranged reads and bytes need to be fetched to restore
+///
+/// Contiguous blobs within a pack are counted as a single ranged read. Blobs of files which
+/// already exist at the destination are counted as well, so the numbers are an upper bound.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `packs` - The packs needed for the restore
+/// * `ls` - The nodes to restore
+fn print_plan<P, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    packs: Vec<PackId>,
+    ls: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+) -> Result<()> {
+    let packs: BTreeSet<_> = packs.into_iter().collect();
+    let mut blobs = BTreeSet::new();
+    let mut ranges: BTreeMap<PackId, Vec<(u32, u32)>> = BTreeMap::new();
+    for item in ls {
+        let (_, node) = item?;
+        for id in node.content.iter().flatten() {
+            if !blobs.insert(*id) {
+                continue;
+            }
+            let entry = repo.get_index_entry(id)?;
+            if packs.contains(&entry.pack) {
+                ranges
+                    .entry(entry.pack)
+                    .or_default()
+                    .push((entry.offset, entry.length));
+            }
+        }
+    }
+
+    let mut reads = 0;
+    let mut size = 0;
+    for pack_ranges in ranges.values_mut() {
+        pack_ranges.sort_unstable();
+        let mut end = None;
+        for (offset, length) in pack_ranges.iter() {
+            if end != Some(*offset) {
+                reads += 1;
+            }
+            end = Some(offset + length);
+            size += u64::from(*length);
+        }
+    }
+
+    let mut table = table_right_from(1, ["Backend", "Packs", "Ranged reads", "Bytes"]);
+    _ = table.add_row([
+        repo.name.clone(),
+        packs.len().to_string(),
+        reads.to_string(),
+        bytes_size_to_string(size),
+    ]);
+    println!("{table}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use clap::Parser;
+    use rstest::rstest;
+
+    /// Parse the restore command from the given arguments
+    fn cmd(args: &[&str]) -> RestoreCmd {
+        RestoreCmd::try_parse_from(["restore", "latest", "dest"].iter().chain(args)).unwrap()
+    }
+
+    #[rstest]
+    #[case(&[], Some(ExistingPolicy::Overwrite))]
+    #[case(&["--delete"], Some(ExistingPolicy::Overwrite))]
+    #[case(&["--existing", "fail", "--delete"], Some(ExistingPolicy::Fail))]
+    #[case(&["--existing", "skip"], Some(ExistingPolicy::Skip))]
+    #[case(&["--existing", "skip", "--delete"], None)]
+    #[case(&["--existing", "rename", "--delete"], None)]
+    #[case(&["--backup-existing", ".bak"], Some(ExistingPolicy::Rename))]
+    #[case(&["--backup-existing", ".bak", "--delete"], None)]
+    fn existing_policy_is_checked(#[case] args: &[&str], #[case] expected: Option<ExistingPolicy>) {
+        assert_eq!(cmd(args).existing_policy().ok(), expected);
+    }
+
+    #[test]
+    fn backup_path_appends_number() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("file");
+        fs::write(&path, "new")?;
+        assert_eq!(backup_path(&path, ".orig"), dir.path().join("file.orig"));
+
+        fs::write(dir.path().join("file.orig"), "old")?;
+        assert_eq!(backup_path(&path, ".orig"), dir.path().join("file.orig.1"));
+
+        fs::write(dir.path().join("file.orig.1"), "older")?;
+        assert_eq!(backup_path(&path, ".orig"), dir.path().join("file.orig.2"));
+        assert_eq!(backup_path(&path, ".bak"), dir.path().join("file.bak"));
+        Ok(())
+    }
+
+    /// Create the given existing files in a temporary directory
+    fn existing(dir: &Path, names: &[&str]) -> Result<Vec<(PathBuf, PathBuf)>> {
+        names
+            .iter()
+            .map(|name| {
+                let dest = dir.join(name);
+                fs::write(&dest, name)?;
+                Ok((PathBuf::from(name), dest))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn skip_returns_existing_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let existing = existing(dir.path(), &["a", "b"])?;
+        let skipped = cmd(&[]).apply_policy(ExistingPolicy::Skip, existing, false)?;
+        assert_eq!(
+            skipped,
+            BTreeSet::from([PathBuf::from("a"), PathBuf::from("b")])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fail_aborts_only_for_existing_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cmd = cmd(&["--existing", "fail"]);
+        assert!(cmd
+            .apply_policy(ExistingPolicy::Fail, Vec::new(), false)?
+            .is_empty());
+
+        let existing = existing(dir.path(), &["a"])?;
+        assert!(cmd
+            .apply_policy(ExistingPolicy::Fail, existing, false)
+            .is_err());
+        assert!(dir.path().join("a").exists());
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn rename_moves_existing_files(#[case] dry_run: bool) -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("a.bak"), "old")?;
+        let existing = existing(dir.path(), &["a", "b"])?;
+        let cmd = cmd(&["--backup-existing", ".bak"]);
+        assert!(cmd
+            .apply_policy(ExistingPolicy::Rename, existing, dry_run)?
+            .is_empty());
+
+        assert_eq!(dir.path().join("a").exists(), dry_run);
+        assert_eq!(dir.path().join("b").exists(), dry_run);
+        assert_eq!(fs::read_to_string(dir.path().join("a.bak"))?, "old");
+        if !dry_run {
+            assert_eq!(fs::read_to_string(dir.path().join("a.bak.1"))?, "a");
+            assert_eq!(fs::read_to_string(dir.path().join("b.bak"))?, "b");
+        }
+        Ok(())
+    }
 }
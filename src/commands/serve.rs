@@ -0,0 +1,585 @@
+//! `serve` subcommand
+//!
+//! Serves the repository backend using the REST protocol of restic's rest-server, such that
+//! other rustic or restic instances can use it with a `rest:` repository.
+//!
+//! The global `--read-only` and the `--append-only` repository option are respected, as they
+//! are applied to the served backend. In append-only mode, all delete requests are refused.
+//!
+//! Clients can be authenticated using basic auth with users from a htpasswd file. Without
+//! authentication, the server can only be bound to a loopback address.
+//!
+//! To answer `HEAD` requests, the files of a type are listed once and the listing is kept up to
+//! date by the requests. Files added to the repository by other programs while serving are only
+//! found after a client listed the files of that type.
+
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{status_err, systemd, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use clap::ValueHint;
+use log::{debug, info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio_stream::wrappers::TcpListenerStream;
+use warp::{
+    http::{header, Method, Response, StatusCode},
+    path::FullPath,
+    Filter,
+};
+
+use rustic_core::{FileType, Id, ReadBackend, RusticResult, WriteBackend};
+
+/// Media type of the version 2 of the REST protocol
+const API_V2: &str = "application/vnd.x.restic.rest.v2";
+
+/// `serve` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct ServeCmd {
+    /// Address to bind the REST server to. Not used if a socket is passed by systemd socket
+    /// activation
+    #[clap(long, value_name = "ADDRESS", default_value = "localhost:8000")]
+    address: String,
+
+    /// Only allow users from the given htpasswd file, using basic auth. Only bcrypt password
+    /// hashes are supported. This is needed to bind to an address which is not a loopback address
+    #[clap(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    htpasswd: Option<PathBuf>,
+}
+
+impl Runnable for ServeCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// A request to the REST server
+struct Request {
+    method: Method,
+    path: String,
+    query: String,
+    range: Option<String>,
+    accept: Option<String>,
+    authorization: Option<String>,
+    body: Bytes,
+}
+
+/// Users allowed to access the REST server, read from a htpasswd file
+struct Htpasswd {
+    /// The bcrypt hashes of the passwords per user
+    users: HashMap<String, String>,
+    /// Hashes of the credentials which have already been verified, as bcrypt is slow on purpose
+    verified: Mutex<HashSet<Vec<u8>>>,
+}
+
+impl Htpasswd {
+    /// Read the users from a htpasswd file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the htpasswd file
+    fn from_file(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read_to_string(path).with_context(|| format!("error reading {path:?}"))?;
+        let mut users = HashMap::new();
+        for line in data
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let Some((user, hash)) = line.split_once(':') else {
+                bail!("invalid line in {path:?}: {line:?}");
+            };
+            if hash.starts_with("$2") {
+                _ = users.insert(user.to_string(), hash.to_string());
+            } else {
+                warn!(
+                    "ignoring user {user} in {path:?}: only bcrypt password hashes are supported"
+                );
+            }
+        }
+        if users.is_empty() {
+            bail!("no users found in {path:?}");
+        }
+        Ok(Self {
+            users,
+            verified: Mutex::default(),
+        })
+    }
+
+    /// Check if the credentials of a basic auth header belong to a user
+    fn is_authorized(&self, authorization: Option<&str>) -> bool {
+        let Some(credentials) = authorization
+            .and_then(|auth| auth.strip_prefix("Basic "))
+            .and_then(|credentials| STANDARD.decode(credentials.trim()).ok())
+            .and_then(|credentials| String::from_utf8(credentials).ok())
+        else {
+            return false;
+        };
+        let Some((user, password)) = credentials.split_once(':') else {
+            return false;
+        };
+        let Some(hash) = self.users.get(user) else {
+            return false;
+        };
+        let key = Sha256::digest(credentials.as_bytes()).to_vec();
+        if self.verified.lock().unwrap().contains(&key) {
+            return true;
+        }
+        if bcrypt::verify(password, hash).unwrap_or(false) {
+            _ = self.verified.lock().unwrap().insert(key);
+            return true;
+        }
+        false
+    }
+}
+
+/// The REST server
+struct Server {
+    /// The backend to serve
+    be: Arc<dyn WriteBackend>,
+    /// The users allowed to access the server, if authentication is needed
+    htpasswd: Option<Htpasswd>,
+    /// Whether delete requests are refused
+    append_only: bool,
+    /// The sizes of the files per file type, listed when a size of that type is first needed
+    sizes: Mutex<HashMap<&'static str, HashMap<Id, u32>>>,
+}
+
+/// An entry of a file listing in version 2 of the REST protocol
+#[derive(Serialize)]
+struct ListEntry {
+    name: String,
+    size: u32,
+}
+
+impl ServeCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let be = config.repository.to_backends()?.repository();
+
+        let listener = systemd::listener()?;
+        let addr = match &listener {
+            Some(listener) => listener.local_addr()?,
+            None => self
+                .address
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| anyhow!("no address given"))?,
+        };
+        let htpasswd = self
+            .htpasswd
+            .as_deref()
+            .map(Htpasswd::from_file)
+            .transpose()?;
+        if htpasswd.is_none() && !addr.ip().is_loopback() {
+            bail!("serving on {addr} without authentication is not allowed, use --htpasswd.");
+        }
+
+        info!("serving {} on {addr}...", be.location());
+        let server = Arc::new(Server {
+            be,
+            htpasswd,
+            append_only: config.repository.append_only,
+            sizes: Mutex::default(),
+        });
+        let query = warp::query::raw().or(warp::any().map(String::new)).unify();
+        let routes = warp::method()
+            .and(warp::path::full())
+            .and(query)
+            .and(warp::header::optional::<String>(header::RANGE.as_str()))
+            .and(warp::header::optional::<String>(header::ACCEPT.as_str()))
+            .and(warp::header::optional::<String>(
+                header::AUTHORIZATION.as_str(),
+            ))
+            .and(warp::body::bytes())
+            .and_then(
+                move |method, path: FullPath, query, range, accept, authorization, body| {
+                    let server = server.clone();
+                    let req = Request {
+                        method,
+                        path: path.as_str().to_string(),
+                        query,
+                        range,
+                        accept,
+                        authorization,
+                        body,
+                    };
+                    async move {
+                        tokio::task::spawn_blocking(move || server.handle(req))
+                            .await
+                            .map_err(|_| warp::reject())
+                    }
+                },
+            );
+
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                _ = tokio::spawn(systemd::keep_alive_task());
+                let server = warp::serve(routes);
+                if let Some(listener) = listener {
+                    let listener = tokio::net::TcpListener::from_std(listener)?;
+                    systemd::ready(&format!("serving on {addr}"));
+                    server.run_incoming(TcpListenerStream::new(listener)).await;
+                } else {
+                    let (addr, server) = server.try_bind_ephemeral(addr)?;
+                    systemd::ready(&format!("serving on {addr}"));
+                    server.await;
+                }
+                Ok::<_, anyhow::Error>(())
+            })?;
+
+        Ok(())
+    }
+}
+
+/// Get the file type for the directory name used in the REST protocol
+fn file_type(dirname: &str) -> Option<FileType> {
+    [
+        FileType::Key,
+        FileType::Snapshot,
+        FileType::Index,
+        FileType::Pack,
+    ]
+    .into_iter()
+    .find(|tpe| tpe.dirname() == dirname)
+}
+
+/// Parse a HTTP range header of the form `bytes=START-END` into offset and length
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let start: u32 = start.parse().ok()?;
+    let end: u32 = end.parse().ok()?;
+    (end >= start).then_some((start, end - start + 1))
+}
+
+/// Create a response with the given status and body
+fn response(status: StatusCode, body: impl Into<Bytes>) -> Response<Bytes> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    response
+}
+
+impl Server {
+    /// Handle a request to the REST server
+    ///
+    /// # Arguments
+    ///
+    /// * `req` - The request
+    fn handle(&self, req: Request) -> Response<Bytes> {
+        debug!("{} {}", req.method, req.path);
+        if let Some(htpasswd) = &self.htpasswd {
+            if !htpasswd.is_authorized(req.authorization.as_deref()) {
+                info!("{} {} unauthorized", req.method, req.path);
+                let mut response = response(StatusCode::UNAUTHORIZED, Bytes::new());
+                _ = response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    header::HeaderValue::from_static("Basic realm=\"rustic\""),
+                );
+                return response;
+            }
+        }
+
+        let be = &self.be;
+        let method = req.method.clone();
+        let is_read = method == Method::GET || method == Method::HEAD;
+        let segments: Vec<_> = req.path.trim_matches('/').split('/').collect();
+        let result = match segments.as_slice() {
+            [""] if method == Method::POST && req.query.split('&').any(|q| q == "create=true") => {
+                be.create().map(|()| response(StatusCode::OK, Bytes::new()))
+            }
+            ["config"] if is_read => self.read(FileType::Config, &Id::default(), &req),
+            ["config"] if method == Method::POST => {
+                self.write(FileType::Config, &Id::default(), true, &req)
+            }
+            [dir] if method == Method::GET => match file_type(dir) {
+                Some(tpe) => be.list_with_size(tpe).map(|list| {
+                    _ = self
+                        .sizes
+                        .lock()
+                        .unwrap()
+                        .insert(tpe.dirname(), list.iter().copied().collect());
+                    list_response(list, &req)
+                }),
+                None => return response(StatusCode::NOT_FOUND, Bytes::new()),
+            },
+            [dir, name] => {
+                let (Some(tpe), Ok(id)) = (file_type(dir), Id::from_hex(name)) else {
+                    return response(StatusCode::NOT_FOUND, Bytes::new());
+                };
+                let cacheable = !matches!(tpe, FileType::Pack);
+                if is_read {
+                    self.read(tpe, &id, &req)
+                } else if method == Method::POST {
+                    self.write(tpe, &id, cacheable, &req)
+                } else if method == Method::DELETE && self.append_only {
+                    info!(
+                        "{} {} refused: repository is append-only",
+                        req.method, req.path
+                    );
+                    return response(StatusCode::FORBIDDEN, Bytes::new());
+                } else if method == Method::DELETE {
+                    be.remove(tpe, &id, cacheable).map(|()| {
+                        self.update_size(tpe, &id, None);
+                        response(StatusCode::OK, Bytes::new())
+                    })
+                } else {
+                    return response(StatusCode::METHOD_NOT_ALLOWED, Bytes::new());
+                }
+            }
+            _ => return response(StatusCode::NOT_FOUND, Bytes::new()),
+        };
+
+        result.unwrap_or_else(|err| {
+            info!("{} {} failed: {err}", req.method, req.path);
+            response(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+        })
+    }
+
+    /// Get the size of a file, `None` if the file doesn't exist
+    ///
+    /// The files of a type are only listed for the first request, afterwards the sizes are kept
+    /// up to date by [`Self::update_size`].
+    fn file_size(&self, tpe: FileType, id: &Id) -> RusticResult<Option<u32>> {
+        let mut sizes = self.sizes.lock().unwrap();
+        let files = match sizes.entry(tpe.dirname()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(self.be.list_with_size(tpe)?.into_iter().collect())
+            }
+        };
+        Ok(files.get(id).copied())
+    }
+
+    /// Update the size of a file if the files of its type were already listed
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The file type
+    /// * `id` - The id of the file
+    /// * `size` - The new size, `None` if the file was removed
+    fn update_size(&self, tpe: FileType, id: &Id, size: Option<u32>) {
+        if let Some(files) = self.sizes.lock().unwrap().get_mut(tpe.dirname()) {
+            match size {
+                Some(size) => {
+                    _ = files.insert(*id, size);
+                }
+                None => {
+                    _ = files.remove(id);
+                }
+            }
+        }
+    }
+
+    /// Write a file with the body of the request
+    fn write(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        req: &Request,
+    ) -> RusticResult<Response<Bytes>> {
+        self.be.write_bytes(tpe, id, cacheable, req.body.clone())?;
+        // files are at most 4GiB, as the backends give their sizes as u32
+        let size = req.body.len().try_into().unwrap_or(u32::MAX);
+        self.update_size(tpe, id, Some(size));
+        Ok(response(StatusCode::OK, Bytes::new()))
+    }
+
+    /// Read a file, respecting the range header of the request
+    ///
+    /// For `HEAD` requests, the file is not read, only its size is returned. If reading fails, it is
+    /// checked whether the file exists, such that only missing files are reported as not found.
+    fn read(&self, tpe: FileType, id: &Id, req: &Request) -> RusticResult<Response<Bytes>> {
+        if req.method == Method::HEAD {
+            let Some(size) = self.file_size(tpe, id)? else {
+                return Ok(response(StatusCode::NOT_FOUND, Bytes::new()));
+            };
+            let mut response = response(StatusCode::OK, Bytes::new());
+            _ = response
+                .headers_mut()
+                .insert(header::CONTENT_LENGTH, size.into());
+            return Ok(response);
+        }
+
+        let cacheable = !matches!(tpe, FileType::Pack);
+        let (status, data) = match req.range.as_deref() {
+            Some(range) => {
+                let Some((offset, length)) = parse_range(range) else {
+                    return Ok(response(StatusCode::RANGE_NOT_SATISFIABLE, Bytes::new()));
+                };
+                (
+                    StatusCode::PARTIAL_CONTENT,
+                    self.be.read_partial(tpe, id, cacheable, offset, length),
+                )
+            }
+            None => (StatusCode::OK, self.be.read_full(tpe, id)),
+        };
+        let data = match data {
+            Ok(data) => data,
+            Err(err) if self.file_size(tpe, id)?.is_some() => return Err(err),
+            Err(err) => {
+                debug!("{} {} not found: {err}", req.method, req.path);
+                return Ok(response(StatusCode::NOT_FOUND, Bytes::new()));
+            }
+        };
+
+        let length = data.len();
+        let mut response = response(status, data);
+        _ = response
+            .headers_mut()
+            .insert(header::CONTENT_LENGTH, length.into());
+        Ok(response)
+    }
+}
+
+/// Create the response for a file listing, using the format requested by the accept header
+fn list_response(list: Vec<(Id, u32)>, req: &Request) -> Response<Bytes> {
+    let v2 = req.accept.as_deref().is_some_and(|a| a.contains(API_V2));
+    let json = if v2 {
+        let list: Vec<_> = list
+            .into_iter()
+            .map(|(id, size)| ListEntry {
+                name: id.to_hex().to_string(),
+                size,
+            })
+            .collect();
+        serde_json::to_vec(&list)
+    } else {
+        let list: Vec<_> = list
+            .into_iter()
+            .map(|(id, _)| id.to_hex().to_string())
+            .collect();
+        serde_json::to_vec(&list)
+    };
+
+    match json {
+        Ok(json) => {
+            let mut response = response(StatusCode::OK, json);
+            let content_type = if v2 { API_V2 } else { "application/json" };
+            _ = response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(content_type),
+            );
+            response
+        }
+        Err(err) => response(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    use crate::backend::mock::MemoryBackend;
+
+    #[rstest]
+    #[case("bytes=0-0", Some((0, 1)))]
+    #[case("bytes=10-19", Some((10, 10)))]
+    #[case("bytes=5-4", None)]
+    #[case("bytes=5-", None)]
+    #[case("bytes=-5", None)]
+    #[case("bytes=a-b", None)]
+    #[case("bytes=0-99999999999", None)]
+    #[case("items=0-1", None)]
+    #[case("0-1", None)]
+    fn parse_range_checks_input(#[case] range: &str, #[case] expected: Option<(u32, u32)>) {
+        assert_eq!(parse_range(range), expected);
+    }
+
+    /// Get the basic auth header for the given credentials
+    fn basic_auth(credentials: &str) -> String {
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+
+    #[rstest]
+    #[case(Some(basic_auth("user:secret")), true)]
+    #[case(Some(basic_auth("user:wrong")), false)]
+    #[case(Some(basic_auth("other:secret")), false)]
+    #[case(Some(basic_auth("plain:secret")), false)]
+    #[case(Some(basic_auth("user")), false)]
+    #[case(Some("Basic !!!".to_string()), false)]
+    #[case(Some("Bearer dXNlcjpzZWNyZXQ=".to_string()), false)]
+    #[case(None, false)]
+    fn is_authorized_checks_credentials(
+        #[case] authorization: Option<String>,
+        #[case] expected: bool,
+    ) -> Result<()> {
+        let file = tempfile::NamedTempFile::new()?;
+        let hash = bcrypt::hash("secret", 4)?;
+        std::fs::write(
+            file.path(),
+            format!("# comment\nuser:{hash}\nplain:secret\n"),
+        )?;
+        let htpasswd = Htpasswd::from_file(file.path())?;
+        assert_eq!(htpasswd.is_authorized(authorization.as_deref()), expected);
+        // verified credentials are remembered
+        assert_eq!(htpasswd.is_authorized(authorization.as_deref()), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn htpasswd_without_bcrypt_users_is_rejected() -> Result<()> {
+        let file = tempfile::NamedTempFile::new()?;
+        std::fs::write(file.path(), "plain:secret\n")?;
+        assert!(Htpasswd::from_file(file.path()).is_err());
+        Ok(())
+    }
+
+    /// Get a request with the given method and path
+    fn request(method: Method, path: &str, body: &'static str) -> Request {
+        Request {
+            method,
+            path: path.to_string(),
+            query: String::new(),
+            range: None,
+            accept: None,
+            authorization: None,
+            body: Bytes::from(body),
+        }
+    }
+
+    #[test]
+    fn head_requests_use_listed_sizes() {
+        let mock = Arc::new(MemoryBackend::default());
+        let server = Server {
+            be: mock.clone(),
+            htpasswd: None,
+            append_only: false,
+            sizes: Mutex::default(),
+        };
+        let path = format!("/snapshots/{:064x}", 1);
+        let head = || server.handle(request(Method::HEAD, &path, ""));
+
+        assert_eq!(head().status(), StatusCode::NOT_FOUND);
+        let calls = mock.calls();
+
+        let response = server.handle(request(Method::POST, &path, "snapshot"));
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = head();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "8");
+
+        let response = server.handle(request(Method::DELETE, &path, ""));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(head().status(), StatusCode::NOT_FOUND);
+
+        // the files were only listed for the first request
+        assert_eq!(mock.calls(), calls + 2);
+    }
+}
@@ -1,20 +1,26 @@
 //! `smapshot` subcommand
 
+use std::collections::{BTreeMap, BTreeSet};
+
 use crate::{
     commands::open_repository,
+    filtering::keep_latest,
     helpers::{bold_cell, bytes_size_to_string, table, table_right_from},
     status_err, Application, RUSTIC_APP,
 };
 
 use abscissa_core::{Command, Runnable, Shutdown};
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use comfy_table::Cell;
 use humantime::format_duration;
 use itertools::Itertools;
+use serde::Serialize;
 
 use rustic_core::{
-    repofile::{DeleteOption, SnapshotFile},
-    SnapshotGroupCriterion,
+    repofile::{DeleteOption, SnapshotFile, SnapshotId, Tree},
+    DataId, IndexedFull, Progress, ProgressBars, Repository, SnapshotGroup, SnapshotGroupCriterion,
+    TreeId,
 };
 
 #[cfg(feature = "tui")]
@@ -36,6 +42,10 @@ pub(crate) struct SnapshotCmd {
     )]
     group_by: SnapshotGroupCriterion,
 
+    /// Only show the latest N snapshots of each group
+    #[clap(long, value_name = "N")]
+    latest: Option<usize>,
+
     /// Show detailed information about snapshots
     #[arg(long)]
     long: bool,
@@ -44,6 +54,15 @@ pub(crate) struct SnapshotCmd {
     #[clap(long, conflicts_with = "long")]
     json: bool,
 
+    /// Show parent/child relations and group membership of the snapshots as json graph
+    #[clap(long, conflicts_with_all = ["long", "json"])]
+    graph_json: bool,
+
+    /// Show the size which would be freed by only removing each snapshot (slow, as all snapshots
+    /// need to be read)
+    #[clap(long, conflicts_with = "json")]
+    unique_size: bool,
+
     /// Show all snapshots instead of summarizing identical follow-up snapshots
     #[clap(long, conflicts_with_all = &["long", "json"])]
     all: bool,
@@ -54,6 +73,154 @@ pub(crate) struct SnapshotCmd {
     pub interactive: bool,
 }
 
+/// Snapshot lineage as printed by `snapshots --graph-json`
+#[derive(Serialize)]
+struct SnapshotGraph<'a> {
+    /// The snapshot groups
+    groups: Vec<&'a SnapshotGroup>,
+    /// The snapshots
+    nodes: Vec<GraphNode<'a>>,
+    /// Parent/child relations between snapshots
+    edges: Vec<GraphEdge>,
+}
+
+/// A snapshot within a [`SnapshotGraph`]
+#[derive(Serialize)]
+struct GraphNode<'a> {
+    id: SnapshotId,
+    time: &'a DateTime<Local>,
+    /// Index of the group of the snapshot
+    group: usize,
+    parent: Option<SnapshotId>,
+    /// Whether the parent snapshot no longer exists in the repository
+    parent_missing: bool,
+}
+
+/// A parent/child relation within a [`SnapshotGraph`]
+#[derive(Serialize)]
+struct GraphEdge {
+    parent: SnapshotId,
+    child: SnapshotId,
+}
+
+impl<'a> SnapshotGraph<'a> {
+    /// Create the graph of grouped snapshots
+    ///
+    /// # Arguments
+    ///
+    /// * `groups` - The snapshot groups
+    /// * `existing` - The ids of all snapshots of the repository
+    fn new(
+        groups: &'a [(SnapshotGroup, Vec<SnapshotFile>)],
+        existing: &BTreeSet<SnapshotId>,
+    ) -> Self {
+        let mut graph = Self {
+            groups: Vec::new(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        for (index, (group, snapshots)) in groups.iter().enumerate() {
+            graph.groups.push(group);
+            for sn in snapshots {
+                graph.nodes.push(GraphNode {
+                    id: sn.id,
+                    time: &sn.time,
+                    group: index,
+                    parent: sn.parent,
+                    parent_missing: sn.parent.is_some_and(|parent| !existing.contains(&parent)),
+                });
+                if let Some(parent) = sn.parent {
+                    graph.edges.push(GraphEdge {
+                        parent,
+                        child: sn.id,
+                    });
+                }
+            }
+        }
+        graph
+    }
+}
+
+/// Snapshot using a blob, `None` if the blob is used by more than one snapshot
+type Owner = Option<SnapshotId>;
+
+/// Set the owner of the given tree and of all blobs within it
+///
+/// Trees are only walked when they are found first and when they turn out to be shared. In the
+/// latter case, all blobs within the tree are marked as shared.
+///
+/// # Arguments
+///
+/// * `get_tree` - Get a tree by its id
+/// * `id` - The id of the tree
+/// * `owner` - The snapshot referencing the tree, `None` if the tree is shared
+/// * `trees` - The owners of all trees found so far
+/// * `data` - The owners of all data blobs found so far
+fn add_owner(
+    get_tree: &impl Fn(&TreeId) -> Result<Tree>,
+    id: TreeId,
+    owner: Owner,
+    trees: &mut BTreeMap<TreeId, Owner>,
+    data: &mut BTreeMap<DataId, Owner>,
+) -> Result<()> {
+    let owner = match trees.get(&id) {
+        None => owner,
+        Some(current) if current.is_none() || *current == owner => return Ok(()),
+        Some(_) => None,
+    };
+    _ = trees.insert(id, owner);
+    for node in get_tree(&id)?.nodes {
+        for blob in node.content.iter().flatten() {
+            let current = data.entry(*blob).or_insert(owner);
+            if *current != owner {
+                *current = None;
+            }
+        }
+        if let Some(subtree) = node.subtree {
+            add_owner(get_tree, subtree, owner, trees, data)?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the stored size of the blobs which are only used by a single snapshot for all snapshots
+///
+/// This is the size which is freed when only this snapshot is removed.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+fn unique_sizes<P, S: IndexedFull>(repo: &Repository<P, S>) -> Result<BTreeMap<SnapshotId, u64>> {
+    let snaps = repo.get_all_snapshots()?;
+    let p = RUSTIC_APP
+        .config()
+        .global
+        .progress_options
+        .progress_counter("counting blob references...");
+    p.set_length(snaps.len().try_into()?);
+    let mut trees = BTreeMap::new();
+    let mut data = BTreeMap::new();
+    let get_tree = |id: &TreeId| -> Result<Tree> { Ok(repo.get_tree(id)?) };
+    for sn in &snaps {
+        add_owner(&get_tree, sn.tree, Some(sn.id), &mut trees, &mut data)?;
+        p.inc(1);
+    }
+    p.finish();
+
+    let mut sizes: BTreeMap<_, _> = snaps.iter().map(|sn| (sn.id, 0)).collect();
+    for (id, owner) in &trees {
+        if let Some(size) = owner.and_then(|owner| sizes.get_mut(&owner)) {
+            *size += u64::from(repo.get_index_entry(id)?.length);
+        }
+    }
+    for (id, owner) in &data {
+        if let Some(size) = owner.and_then(|owner| sizes.get_mut(&owner)) {
+            *size += u64::from(repo.get_index_entry(id)?.length);
+        }
+    }
+    Ok(sizes)
+}
+
 impl Runnable for SnapshotCmd {
     fn run(&self) {
         if let Err(err) = self.inner_run() {
@@ -73,9 +240,12 @@ impl SnapshotCmd {
         let config = RUSTIC_APP.config();
         let repo = open_repository(&config.repository)?;
 
-        let groups = repo.get_snapshot_group(&self.ids, self.group_by, |sn| {
+        let mut groups = repo.get_snapshot_group(&self.ids, self.group_by, |sn| {
             config.snapshot_filter.matches(sn)
         })?;
+        if let Some(n) = self.latest {
+            keep_latest(&mut groups, n);
+        }
 
         if self.json {
             let mut stdout = std::io::stdout();
@@ -83,6 +253,32 @@ impl SnapshotCmd {
             return Ok(());
         }
 
+        if self.graph_json {
+            let existing: BTreeSet<_> = repo.get_all_snapshots()?.iter().map(|sn| sn.id).collect();
+            let graph = SnapshotGraph::new(&groups, &existing);
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &graph)?;
+            return Ok(());
+        }
+
+        let unique = if self.unique_size {
+            unique_sizes(&repo.to_indexed()?)?
+        } else {
+            BTreeMap::new()
+        };
+        let unique_size = |sn: &SnapshotFile| {
+            unique
+                .get(&sn.id)
+                .map_or_else(|| "?".to_string(), |size| bytes_size_to_string(*size))
+        };
+        let to_row = |sn: &SnapshotFile, count| {
+            let mut row = snap_to_table(sn, count).to_vec();
+            if self.unique_size {
+                row.push(unique_size(sn));
+            }
+            row
+        };
+
         let mut total_count = 0;
         for (group, mut snapshots) in groups {
             if !group.is_empty() {
@@ -95,25 +291,29 @@ impl SnapshotCmd {
                 for snap in snapshots {
                     let mut table = table();
 
-                    let add_entry = |title: &str, value: String| {
+                    let mut add_entry = |title: &str, value: String| {
                         _ = table.add_row([bold_cell(title), Cell::new(value)]);
                     };
-                    fill_table(&snap, add_entry);
+                    fill_table(&snap, &mut add_entry);
+                    if self.unique_size {
+                        add_entry("Unique size", unique_size(&snap));
+                    }
 
                     println!("{table}");
                     println!();
                 }
             } else {
-                let mut table = table_right_from(
-                    6,
-                    [
-                        "ID", "Time", "Host", "Label", "Tags", "Paths", "Files", "Dirs", "Size",
-                    ],
-                );
+                let mut titles = vec![
+                    "ID", "Time", "Host", "Label", "Tags", "Paths", "Files", "Dirs", "Size",
+                ];
+                if self.unique_size {
+                    titles.push("Unique");
+                }
+                let mut table = table_right_from(6, titles);
 
                 if self.all {
                     // Add all snapshots to output table
-                    _ = table.add_rows(snapshots.into_iter().map(|sn| snap_to_table(&sn, 0)));
+                    _ = table.add_rows(snapshots.into_iter().map(|sn| to_row(&sn, 0)));
                 } else {
                     // Group snapshts by treeid and output into table
                     _ = table.add_rows(
@@ -121,7 +321,7 @@ impl SnapshotCmd {
                             .into_iter()
                             .chunk_by(|sn| sn.tree)
                             .into_iter()
-                            .map(|(_, mut g)| snap_to_table(&g.next().unwrap(), g.count())),
+                            .map(|(_, mut g)| to_row(&g.next().unwrap(), g.count())),
                     );
                 }
                 println!("{table}");
@@ -248,3 +448,80 @@ pub fn fill_table(snap: &SnapshotFile, mut add_entry: impl FnMut(&str, String))
         add_entry("Description", description.clone());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ffi::OsStr;
+
+    use rustic_core::{
+        repofile::{Metadata, Node, NodeType},
+        Id,
+    };
+
+    /// Get an id for tests
+    fn id<T: From<Id>>(n: u8) -> T {
+        Id::from_hex(&format!("{n:064x}")).unwrap().into()
+    }
+
+    /// Create a tree containing the given data blobs and subtrees
+    fn tree(blobs: &[u8], subtrees: &[u8]) -> Tree {
+        let mut file = Node::new_node(OsStr::new("file"), NodeType::File, Metadata::default());
+        file.content = Some(blobs.iter().map(|n| id(*n)).collect());
+        let mut nodes = vec![file];
+        for n in subtrees {
+            let mut dir = Node::new_node(OsStr::new("dir"), NodeType::Dir, Metadata::default());
+            dir.subtree = Some(id(*n));
+            nodes.push(dir);
+        }
+        Tree { nodes }
+    }
+
+    #[test]
+    fn add_owner_marks_shared_subtrees() -> Result<()> {
+        // snapshot 1 uses tree 1 and snapshot 2 uses tree 2, both contain tree 3 which contains
+        // tree 4; the shared trees are first found owned by snapshot 1
+        let trees = BTreeMap::from([
+            (1, tree(&[11], &[3])),
+            (2, tree(&[12], &[3])),
+            (3, tree(&[13], &[4])),
+            (4, tree(&[14, 11], &[])),
+        ]);
+        let get_tree = |tree_id: &TreeId| -> Result<Tree> {
+            Ok(trees
+                .iter()
+                .find(|(n, _)| id::<TreeId>(**n) == *tree_id)
+                .map(|(_, tree)| tree.clone())
+                .unwrap())
+        };
+
+        let (sn1, sn2) = (id::<SnapshotId>(1), id::<SnapshotId>(2));
+        let mut tree_owners = BTreeMap::new();
+        let mut data_owners = BTreeMap::new();
+        add_owner(
+            &get_tree,
+            id(1),
+            Some(sn1),
+            &mut tree_owners,
+            &mut data_owners,
+        )?;
+        add_owner(
+            &get_tree,
+            id(2),
+            Some(sn2),
+            &mut tree_owners,
+            &mut data_owners,
+        )?;
+
+        let expected_trees = [(1, Some(sn1)), (2, Some(sn2)), (3, None), (4, None)];
+        for (n, owner) in expected_trees {
+            assert_eq!(tree_owners[&id::<TreeId>(n)], owner, "tree {n}");
+        }
+        let expected_data = [(11, None), (12, Some(sn2)), (13, None), (14, None)];
+        for (n, owner) in expected_data {
+            assert_eq!(data_owners[&id::<DataId>(n)], owner, "data blob {n}");
+        }
+        Ok(())
+    }
+}
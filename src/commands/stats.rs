@@ -0,0 +1,200 @@
+//! `stats` subcommand
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    commands::{du::Blobs, open_repository_indexed},
+    helpers::{bytes_size_to_string, table_right_from},
+    status_err, Application, RUSTIC_APP,
+};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::Result;
+use serde::Serialize;
+
+use rustic_core::{IndexedFull, PackedId, Progress, ProgressBars, Repository, TreeId};
+
+/// `stats` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct StatsCmd {
+    /// Snapshots to show statistics for. If none is given, use filter options to filter from all snapshots.
+    #[clap(value_name = "ID")]
+    ids: Vec<String>,
+
+    /// Show infos in json format
+    #[clap(long)]
+    json: bool,
+}
+
+impl Runnable for StatsCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Statistics about snapshots
+///
+/// This struct is used to serialize infos in `json` format.
+#[derive(Default, Serialize)]
+struct Stats {
+    /// Number of snapshots
+    snapshots: u64,
+    /// Number of files, summed over all snapshots
+    files: u64,
+    /// Size of all files when restoring all snapshots
+    restore_size: u64,
+    /// Number of distinct tree blobs
+    tree_blobs: u64,
+    /// Number of distinct data blobs
+    data_blobs: u64,
+    /// Number of packs containing the blobs
+    packs: u64,
+    /// Deduplicated size of all blobs before compression
+    raw_size: u64,
+    /// Deduplicated size of all blobs as stored in the repository
+    stored_size: u64,
+    /// Restore size divided by raw size
+    dedup_ratio: f64,
+    /// Raw size divided by stored size
+    compression_ratio: f64,
+}
+
+/// Get the number of files and their total size within a tree
+///
+/// The results are memoized per tree, so trees shared by several snapshots are only read once.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `id` - The id of the tree
+/// * `sizes` - Already computed results
+/// * `blobs` - Blobs to which all blobs found are added
+fn tree_size<P, S: IndexedFull>(
+    repo: &Repository<P, S>,
+    id: TreeId,
+    sizes: &mut BTreeMap<TreeId, (u64, u64)>,
+    blobs: &mut Blobs,
+) -> Result<(u64, u64)> {
+    if let Some(size) = sizes.get(&id) {
+        return Ok(*size);
+    }
+    let (mut files, mut size) = (0, 0);
+    for node in repo.get_tree(&id)?.nodes {
+        if node.is_file() {
+            files += 1;
+            size += node.meta.size;
+        }
+        blobs.add_node(&node);
+        if let Some(subtree) = node.subtree {
+            let (sub_files, sub_size) = tree_size(repo, subtree, sizes, blobs)?;
+            files += sub_files;
+            size += sub_size;
+        }
+    }
+    _ = blobs.trees.insert(id);
+    _ = sizes.insert(id, (files, size));
+    Ok((files, size))
+}
+
+/// Add the raw and stored sizes of the given blobs and collect their packs
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `ids` - The ids of the blobs
+/// * `stats` - The statistics to add the sizes to
+/// * `packs` - Packs to which the packs of the blobs are added
+fn add_blobs<P, S: IndexedFull, T: PackedId>(
+    repo: &Repository<P, S>,
+    ids: &BTreeSet<T>,
+    stats: &mut Stats,
+    packs: &mut BTreeSet<String>,
+) -> Result<()> {
+    for id in ids {
+        let entry = repo.get_index_entry(id)?;
+        stats.raw_size += u64::from(entry.data_length());
+        stats.stored_size += u64::from(entry.length);
+        _ = packs.insert(entry.pack.to_hex().to_string());
+    }
+    Ok(())
+}
+
+/// Ratio of two sizes, 0 if the divisor is 0
+#[allow(clippy::cast_precision_loss)]
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+impl StatsCmd {
+    fn inner_run(&self) -> Result<()> {
+        let config = RUSTIC_APP.config();
+        let repo = open_repository_indexed(&config.repository)?;
+
+        let snapshots = if self.ids.is_empty() {
+            repo.get_matching_snapshots(|sn| config.snapshot_filter.matches(sn))?
+        } else {
+            repo.get_snapshots(&self.ids)?
+        };
+
+        let p = config
+            .global
+            .progress_options
+            .progress_counter("scanning snapshots...");
+        p.set_length(snapshots.len().try_into()?);
+        let mut stats = Stats::default();
+        let mut sizes = BTreeMap::new();
+        let mut blobs = Blobs::default();
+        for sn in &snapshots {
+            let (files, size) = tree_size(&repo, sn.tree, &mut sizes, &mut blobs)?;
+            stats.snapshots += 1;
+            stats.files += files;
+            stats.restore_size += size;
+            p.inc(1);
+        }
+        p.finish();
+
+        let mut packs = BTreeSet::new();
+        add_blobs(&repo, &blobs.trees, &mut stats, &mut packs)?;
+        add_blobs(&repo, &blobs.data, &mut stats, &mut packs)?;
+        stats.tree_blobs = blobs.trees.len().try_into()?;
+        stats.data_blobs = blobs.data.len().try_into()?;
+        stats.packs = packs.len().try_into()?;
+        stats.dedup_ratio = ratio(stats.restore_size, stats.raw_size);
+        stats.compression_ratio = ratio(stats.raw_size, stats.stored_size);
+
+        if self.json {
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &stats)?;
+            return Ok(());
+        }
+
+        let mut table = table_right_from(1, ["", "Count", "Size"]);
+        _ = table.add_row([
+            "restore size (files)".to_string(),
+            stats.files.to_string(),
+            bytes_size_to_string(stats.restore_size),
+        ]);
+        _ = table.add_row([
+            "raw size (blobs)".to_string(),
+            (stats.tree_blobs + stats.data_blobs).to_string(),
+            bytes_size_to_string(stats.raw_size),
+        ]);
+        _ = table.add_row([
+            "stored size (packs)".to_string(),
+            stats.packs.to_string(),
+            bytes_size_to_string(stats.stored_size),
+        ]);
+        println!("statistics for {} snapshots:", stats.snapshots);
+        println!("{table}");
+        println!("deduplication ratio: {:.2}", stats.dedup_ratio);
+        println!("compression ratio:   {:.2}", stats.compression_ratio);
+        Ok(())
+    }
+}
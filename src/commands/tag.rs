@@ -1,5 +1,7 @@
 //! `tag` subcommand
 
+use std::str::FromStr;
+
 use crate::{commands::open_repository, status_err, Application, RUSTIC_APP};
 
 use abscissa_core::{Command, Runnable, Shutdown};
@@ -38,6 +40,34 @@ pub(crate) struct TagCmd {
     )]
     set: Vec<StringList>,
 
+    /// Rename a tag (can be specified multiple times)
+    #[clap(
+        long,
+        value_name = "OLD=NEW",
+        value_parser = parse_rename,
+        conflicts_with = "set",
+        help_heading = "Tag options"
+    )]
+    rename_tag: Vec<(String, String)>,
+
+    /// Only add tags to snapshots having one of the given tags (can be specified multiple times)
+    #[clap(
+        long,
+        value_name = "TAG",
+        requires = "add",
+        help_heading = "Tag options"
+    )]
+    add_if_tag: Vec<String>,
+
+    /// Only remove tags from snapshots of the given hosts (can be specified multiple times)
+    #[clap(
+        long,
+        value_name = "HOST",
+        requires = "remove",
+        help_heading = "Tag options"
+    )]
+    remove_if_host: Vec<String>,
+
     /// Remove any delete mark
     #[clap(
         long,
@@ -59,6 +89,16 @@ pub(crate) struct TagCmd {
     set_delete_after: Option<humantime::Duration>,
 }
 
+/// Parse a tag rename given as `OLD=NEW`
+fn parse_rename(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+            Ok((old.to_string(), new.to_string()))
+        }
+        _ => Err(format!("invalid tag rename {s:?}, use OLD=NEW")),
+    }
+}
+
 impl Runnable for TagCmd {
     fn run(&self) {
         if let Err(err) = self.inner_run() {
@@ -90,12 +130,30 @@ impl TagCmd {
             (false, false, None) => None,
         };
 
-        let snapshots: Vec<_> = snapshots
-            .into_iter()
-            .filter_map(|mut sn| {
-                sn.modify_sn(self.set.clone(), self.add.clone(), &self.remove, &delete)
-            })
-            .collect();
+        let mut modified = Vec::new();
+        for mut sn in snapshots {
+            let has_tag = |tag: &str| sn.tags.iter().any(|t| t == tag);
+            let mut add =
+                if self.add_if_tag.is_empty() || self.add_if_tag.iter().any(|tag| has_tag(tag)) {
+                    self.add.clone()
+                } else {
+                    Vec::new()
+                };
+            let mut remove =
+                if self.remove_if_host.is_empty() || self.remove_if_host.contains(&sn.hostname) {
+                    self.remove.clone()
+                } else {
+                    Vec::new()
+                };
+            for (old, new) in &self.rename_tag {
+                if has_tag(old) {
+                    remove.push(StringList::from_str(old)?);
+                    add.push(StringList::from_str(new)?);
+                }
+            }
+            modified.extend(sn.modify_sn(self.set.clone(), add, &remove, &delete));
+        }
+        let snapshots = modified;
         let old_snap_ids: Vec<_> = snapshots.iter().map(|sn| sn.id).collect();
 
         match (old_snap_ids.is_empty(), config.global.dry_run) {
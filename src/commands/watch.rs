@@ -0,0 +1,141 @@
+//! `watch` subcommand
+
+use std::{
+    path::PathBuf,
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use crate::{commands::backup::BackupCmd, status_err, systemd, Application, RUSTIC_APP};
+
+use abscissa_core::{Command, Runnable, Shutdown};
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
+use notify::{Event, RecursiveMode, Watcher};
+
+/// `watch` subcommand
+#[derive(clap::Parser, Command, Debug)]
+pub(crate) struct WatchCmd {
+    /// Time without any changes after which a backup is started
+    #[clap(long, value_name = "DURATION", default_value = "10s")]
+    quiescence: humantime::Duration,
+
+    /// Backup options
+    #[clap(flatten)]
+    backup: BackupCmd,
+}
+
+impl Runnable for WatchCmd {
+    fn run(&self) {
+        if let Err(err) = self.inner_run() {
+            status_err!("{}", err);
+            RUSTIC_APP.shutdown(Shutdown::Crash);
+        };
+    }
+}
+
+/// Get the local directories written by rustic itself, i.e. the cache and a local repository
+///
+/// Changes within these directories are ignored, otherwise a backup would trigger the next one.
+fn own_dirs() -> Vec<PathBuf> {
+    let config = RUSTIC_APP.config();
+    config
+        .repository
+        .cache_dir()
+        .into_iter()
+        .chain(config.repository.local_repo_dirs())
+        .map(|dir| dir.canonicalize().unwrap_or(dir))
+        .collect()
+}
+
+/// Check if an event is caused by a change outside of the given directories
+fn is_relevant(event: &notify::Result<Event>, ignored: &[PathBuf]) -> bool {
+    match event {
+        Ok(event) => {
+            event.paths.is_empty()
+                || event
+                    .paths
+                    .iter()
+                    .any(|path| !ignored.iter().any(|dir| path.starts_with(dir)))
+        }
+        Err(_) => true,
+    }
+}
+
+impl WatchCmd {
+    fn inner_run(&self) -> Result<()> {
+        if self.backup.uses_stdin() {
+            bail!("cannot watch stdin.");
+        }
+        let sources = self.backup.source_paths()?;
+        if sources.is_empty() {
+            bail!("no backup source given.");
+        }
+        if sources.iter().any(|source| source.as_os_str() == "-") {
+            bail!("cannot watch stdin.");
+        }
+        let ignored = own_dirs();
+
+        let (tx, rx) = channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        for source in &sources {
+            let source = source.canonicalize().unwrap_or_else(|_| source.clone());
+            watcher.watch(&source, RecursiveMode::Recursive)?;
+        }
+        let names: Vec<_> = sources.iter().map(|s| s.display().to_string()).collect();
+        info!("watching {} for changes...", names.join(", "));
+        systemd::ready(&format!("watching {}", names.join(", ")));
+        // without watchdog, only wake up once in a while
+        let keep_alive_interval = systemd::watchdog_interval().unwrap_or(Duration::from_secs(3600));
+
+        // changes which happened during the last backup
+        let mut changed = false;
+        loop {
+            // wait for the first change
+            while !changed {
+                systemd::keep_alive();
+                match rx.recv_timeout(keep_alive_interval) {
+                    Ok(event) if is_relevant(&event, &ignored) => {
+                        match event {
+                            Ok(event) => debug!("change detected: {event:?}"),
+                            Err(err) => warn!("error watching sources: {err}"),
+                        }
+                        changed = true;
+                    }
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => bail!("watcher stopped unexpectedly."),
+                }
+            }
+
+            // coalesce changes until the sources are quiet
+            let mut deadline = Instant::now() + *self.quiescence;
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                systemd::keep_alive();
+                match rx.recv_timeout(remaining.min(keep_alive_interval)) {
+                    Ok(event) => {
+                        if is_relevant(&event, &ignored) {
+                            deadline = Instant::now() + *self.quiescence;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => bail!("watcher stopped unexpectedly."),
+                }
+            }
+
+            info!("sources changed, starting backup...");
+            systemd::notify("STATUS=running backup");
+            if let Err(err) = self.backup.inner_run() {
+                warn!("backup failed: {err}");
+            }
+            // files changed during the backup may have been read before the change, so back
+            // them up again
+            changed = rx.try_iter().any(|event| is_relevant(&event, &ignored));
+            if changed {
+                info!("sources changed during backup.");
+            } else {
+                info!("watching for changes...");
+                systemd::notify("STATUS=watching for changes");
+            }
+        }
+    }
+}
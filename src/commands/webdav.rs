@@ -5,19 +5,22 @@
 
 use std::{net::ToSocketAddrs, str::FromStr};
 
-use crate::{commands::open_repository_indexed, status_err, Application, RusticConfig, RUSTIC_APP};
+use crate::{
+    commands::open_repository_indexed, status_err, systemd, Application, RusticConfig, RUSTIC_APP,
+};
 use abscissa_core::{config::Override, Command, FrameworkError, Runnable, Shutdown};
 use anyhow::{anyhow, Result};
 use dav_server::{warp::dav_handler, DavHandler};
 use merge::Merge;
 use serde::{Deserialize, Serialize};
+use tokio_stream::wrappers::TcpListenerStream;
 
 use rustic_core::vfs::{FilePolicy, IdenticalSnapshot, Latest, Vfs};
 
 #[derive(Clone, Command, Default, Debug, clap::Parser, Serialize, Deserialize, Merge)]
 #[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct WebDavCmd {
-    /// Address to bind the webdav server to. [default: "localhost:8000"]
+    /// Address to bind the webdav server to, not used if a socket is passed by systemd socket activation. [default: "localhost:8000"]
     #[clap(long, value_name = "ADDRESS")]
     address: Option<String>,
 
@@ -99,14 +102,18 @@ impl WebDavCmd {
             Vfs::from_snapshots(snapshots, &path_template, &time_template, latest, identical)?
         };
 
-        let addr = config
-            .webdav
-            .address
-            .clone()
-            .unwrap_or_else(|| "localhost:8000".to_string())
-            .to_socket_addrs()?
-            .next()
-            .ok_or_else(|| anyhow!("no address given"))?;
+        let listener = systemd::listener()?;
+        let addr = match &listener {
+            Some(listener) => listener.local_addr()?,
+            None => config
+                .webdav
+                .address
+                .clone()
+                .unwrap_or_else(|| "localhost:8000".to_string())
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| anyhow!("no address given"))?,
+        };
 
         let file_access = config.webdav.file_access.as_ref().map_or_else(
             || {
@@ -127,8 +134,19 @@ impl WebDavCmd {
             .enable_all()
             .build()?
             .block_on(async {
-                warp::serve(dav_handler(dav_server)).run(addr).await;
-            });
+                _ = tokio::spawn(systemd::keep_alive_task());
+                let server = warp::serve(dav_handler(dav_server));
+                if let Some(listener) = listener {
+                    let listener = tokio::net::TcpListener::from_std(listener)?;
+                    systemd::ready(&format!("serving webdav on {addr}"));
+                    server.run_incoming(TcpListenerStream::new(listener)).await;
+                } else {
+                    let (addr, server) = server.try_bind_ephemeral(addr)?;
+                    systemd::ready(&format!("serving webdav on {addr}"));
+                    server.await;
+                }
+                Ok::<_, anyhow::Error>(())
+            })?;
 
         Ok(())
     }
@@ -5,27 +5,35 @@
 //! for specifying it.
 
 pub(crate) mod progress_options;
+pub(crate) mod retry_options;
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use abscissa_core::config::Config;
 use abscissa_core::path::AbsPathBuf;
 use abscissa_core::FrameworkError;
+use anyhow::{bail, Result};
 use clap::{Parser, ValueHint};
 use directories::ProjectDirs;
 use itertools::Itertools;
 use log::Level;
 use merge::Merge;
 use rustic_backend::BackendOptions;
-use rustic_core::RepositoryOptions;
+use rustic_core::{RepositoryBackends, RepositoryOptions};
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "webdav")]
 use crate::commands::webdav::WebDavCmd;
 use crate::{
+    backend::{appendonly, readonly, retry::with_retries},
     commands::{backup::BackupCmd, copy::CopyCmd, forget::ForgetOptions},
-    config::progress_options::ProgressOptions,
+    config::{progress_options::ProgressOptions, retry_options::RetryOptions},
     filtering::SnapshotFilter,
+    Application, RUSTIC_APP,
 };
 
 /// Rustic Configuration
@@ -65,10 +73,16 @@ pub struct RusticConfig {
     /// webdav options
     #[clap(skip)]
     pub webdav: WebDavCmd,
+
+    /// Config files which have been read, used to validate them
+    #[clap(skip)]
+    #[serde(skip)]
+    #[merge(strategy = merge::vec::append)]
+    pub(crate) config_files: Vec<PathBuf>,
 }
 
 #[derive(Clone, Default, Debug, Parser, Serialize, Deserialize, Merge)]
-#[serde(default, rename_all = "kebab-case")]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
 pub struct AllRepositoryOptions {
     /// Backend options
     #[clap(flatten)]
@@ -79,6 +93,57 @@ pub struct AllRepositoryOptions {
     #[clap(flatten)]
     #[serde(flatten)]
     pub repo: RepositoryOptions,
+
+    /// Retry options
+    #[clap(flatten)]
+    #[serde(flatten)]
+    pub retry: RetryOptions,
+
+    /// Only allow to add new files to the repository: Removing or overwriting files fails.
+    /// Use this together with backend credentials which don't allow to delete files.
+    #[clap(long, global = true, env = "RUSTIC_APPEND_ONLY")]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    pub append_only: bool,
+}
+
+impl AllRepositoryOptions {
+    /// Create the repository backends, retrying failed operations as configured
+    ///
+    /// If the global option `read-only` is set, the backends refuse all modifications.
+    /// If `append-only` is set, the backends refuse to remove or overwrite files.
+    pub fn to_backends(&self) -> Result<RepositoryBackends> {
+        let backends = with_retries(self.be.to_backends()?, self.retry);
+        Ok(restrict_backends(
+            backends,
+            RUSTIC_APP.config().global.read_only,
+            self.append_only,
+        ))
+    }
+
+    /// Get the base cache directory, if caching is enabled
+    ///
+    /// The cache of a repository is located in a subdirectory named by the repository id.
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        if self.repo.no_cache {
+            return None;
+        }
+        self.repo.cache_dir.clone().or_else(|| {
+            ProjectDirs::from("", "", "rustic").map(|dirs| dirs.cache_dir().to_path_buf())
+        })
+    }
+
+    /// Get the directories of the repository and the hot repository which are local paths
+    pub fn local_repo_dirs(&self) -> Vec<PathBuf> {
+        [&self.be.repository, &self.be.repo_hot]
+            .into_iter()
+            .flatten()
+            .filter_map(|repo| match repo.split_once(':') {
+                Some(("local", path)) => Some(PathBuf::from(path)),
+                Some(("rclone" | "rest" | "opendal", _)) => None,
+                _ => Some(PathBuf::from(repo)),
+            })
+            .collect()
+    }
 }
 
 impl RusticConfig {
@@ -101,6 +166,7 @@ impl RusticConfig {
 
         if let Some(path) = paths.iter().find(|path| path.exists()) {
             merge_logs.push((Level::Info, format!("using config {}", path.display())));
+            self.config_files.push(path.clone());
             let mut config = Self::load_toml_file(AbsPathBuf::canonicalize(path)?)?;
             // if "use_profile" is defined in config file, merge the referenced profiles first
             for profile in &config.global.use_profiles.clone() {
@@ -119,6 +185,63 @@ impl RusticConfig {
         };
         Ok(())
     }
+
+    /// Validate the config
+    ///
+    /// This checks all config files which have been read for errors and unknown keys and checks
+    /// for problems which are not detected when parsing the config file(s), e.g. invalid or
+    /// inaccessible backup sources. The repository is not accessed.
+    ///
+    /// # Errors
+    ///
+    /// If problems are found, an error listing all problems is returned
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        for file in &self.config_files {
+            match config_file_problems(file) {
+                Ok(file_problems) => problems.extend(
+                    file_problems
+                        .into_iter()
+                        .map(|problem| format!("{}: {problem}", file.display())),
+                ),
+                Err(err) => problems.push(format!("{}: {err}", file.display())),
+            }
+        }
+        problems.extend(self.backup.config_problems());
+        if self.repository.be.repo_hot.is_some() && self.repository.be.repository.is_none() {
+            problems.push("repo-hot is given, but no repository!".to_string());
+        }
+        if !problems.is_empty() {
+            bail!("invalid config:\n{}", problems.join("\n"));
+        }
+        Ok(())
+    }
+}
+
+/// Check a config file for invalid values and unknown keys
+///
+/// Invalid values (e.g. durations which can't be parsed) and unknown keys in sections which deny
+/// them are found by parsing the file. Unknown keys in the remaining sections are collected while
+/// parsing.
+///
+/// # Arguments
+///
+/// * `path` - The path of the config file
+///
+/// # Returns
+///
+/// The list of problems found
+fn config_file_problems(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)?;
+    let mut problems = Vec::new();
+    let parsed: Result<RusticConfig, _> =
+        serde_ignored::deserialize(toml::Deserializer::new(&content), |key| {
+            problems.push(format!("unknown key \"{key}\""));
+        });
+    if let Err(err) = parsed {
+        return Ok(vec![err.to_string()]);
+    }
+    Ok(problems)
 }
 
 /// Global options
@@ -144,6 +267,17 @@ pub struct GlobalOptions {
     #[merge(strategy = merge::bool::overwrite_false)]
     pub dry_run: bool,
 
+    /// Refuse to modify the repository: All commands which write to or remove from the repository
+    /// fail. Use this to make sure that a repository is never modified.
+    #[clap(long, global = true, env = "RUSTIC_READ_ONLY")]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    pub read_only: bool,
+
+    /// Validate the config before running the command
+    #[clap(long, global = true, env = "RUSTIC_CHECK_CONFIG")]
+    #[merge(strategy = merge::bool::overwrite_false)]
+    pub check_config: bool,
+
     /// Check if index matches pack files and read pack headers if neccessary
     #[clap(long, global = true, env = "RUSTIC_CHECK_INDEX")]
     #[merge(strategy = merge::bool::overwrite_false)]
@@ -178,6 +312,29 @@ fn extend(left: &mut HashMap<String, String>, right: HashMap<String, String>) {
     left.extend(right);
 }
 
+/// Restrict modifications of the repository backends
+///
+/// `read_only` takes precedence over `append_only`.
+///
+/// # Arguments
+///
+/// * `backends` - The repository backends
+/// * `read_only` - Whether all modifications are refused
+/// * `append_only` - Whether removing or overwriting files is refused
+fn restrict_backends(
+    backends: RepositoryBackends,
+    read_only: bool,
+    append_only: bool,
+) -> RepositoryBackends {
+    if read_only {
+        readonly::read_only(backends)
+    } else if append_only {
+        appendonly::append_only(backends)
+    } else {
+        backends
+    }
+}
+
 /// Get the paths to the config file
 ///
 /// # Arguments
@@ -239,3 +396,87 @@ fn get_global_config_path() -> Option<PathBuf> {
 fn get_global_config_path() -> Option<PathBuf> {
     Some(PathBuf::from("/etc/rustic"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{io::Write, sync::Arc};
+
+    use bytes::Bytes;
+    use rstest::rstest;
+    use rustic_core::{FileType, Id, WriteBackend};
+
+    use crate::backend::mock::MemoryBackend;
+
+    #[rstest]
+    #[case(false, false, true, true)]
+    #[case(false, true, true, false)]
+    #[case(true, false, false, false)]
+    #[case(true, true, false, false)]
+    fn restrict_backends_gives_read_only_precedence(
+        #[case] read_only: bool,
+        #[case] append_only: bool,
+        #[case] can_write: bool,
+        #[case] can_remove: bool,
+    ) {
+        let id = |n: u8| Id::from_hex(&format!("{n:064x}")).unwrap();
+        let mock = Arc::new(MemoryBackend::default());
+        mock.write_bytes(FileType::Snapshot, &id(1), false, Bytes::from("1"))
+            .unwrap();
+        let be = restrict_backends(RepositoryBackends::new(mock, None), read_only, append_only)
+            .repository();
+
+        let written = be.write_bytes(FileType::Snapshot, &id(2), false, Bytes::from("2"));
+        assert_eq!(written.is_ok(), can_write);
+        assert_eq!(
+            be.remove(FileType::Snapshot, &id(1), false).is_ok(),
+            can_remove
+        );
+    }
+
+    #[test]
+    fn config_file_problems_finds_unknown_keys() -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        writeln!(
+            file,
+            "[global]\ndry-run = true\n\n[copy]\ntargets = [\"remote\"]\nunknown = 1"
+        )?;
+        let problems = config_file_problems(file.path())?;
+        assert_eq!(problems, vec!["unknown key \"copy.unknown\"".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn config_file_problems_denies_unknown_repository_keys() -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        writeln!(
+            file,
+            "[repository]\nrepository = \"/tmp/repo\"\nunknown = 1"
+        )?;
+        let problems = config_file_problems(file.path())?;
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("unknown"), "{problems:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn config_file_problems_accepts_empty_values() -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        writeln!(
+            file,
+            "[global]\nuse-profiles = []\n\n[repository]\nrepository = \"/tmp/repo\"\n\n[copy]\ntargets = []"
+        )?;
+        assert!(config_file_problems(file.path())?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn config_file_problems_reports_invalid_values() -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        writeln!(file, "[repository]\nretry-wait = \"soon\"")?;
+        let problems = config_file_problems(file.path())?;
+        assert_eq!(problems.len(), 1);
+        Ok(())
+    }
+}
@@ -1,17 +1,28 @@
 //! Progress Bar Config
 
-use std::{borrow::Cow, fmt::Write, time::Duration};
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 use indicatif::{HumanDuration, ProgressBar, ProgressState, ProgressStyle};
 
 use clap::Parser;
 use merge::Merge;
 
+use log::warn;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 
 use rustic_core::{Progress, ProgressBars};
 
+use crate::systemd;
+
 /// Progress Bar Config
 #[serde_as]
 #[derive(Default, Debug, Parser, Clone, Copy, Deserialize, Serialize, Merge)]
@@ -152,6 +163,7 @@ impl Progress for RusticProgress {
     }
 
     fn inc(&self, inc: u64) {
+        systemd::keep_alive();
         self.0.inc(inc);
     }
 
@@ -159,3 +171,130 @@ impl Progress for RusticProgress {
         self.0.finish_with_message("done");
     }
 }
+
+/// Progress bars which additionally print a json event to stdout when a phase is started or finished
+///
+/// Each non-hidden progress bar is treated as a phase which is named by its prefix.
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseProgressBars<P>(pub P);
+
+impl<P: ProgressBars> PhaseProgressBars<P> {
+    /// Wrap the given progress and emit the start event
+    fn phase(&self, phase: Cow<'static, str>, inner: P::P) -> PhaseProgress<P::P> {
+        let state = Arc::new(PhaseState {
+            phase: Mutex::new(phase),
+            start: Instant::now(),
+            length: AtomicU64::new(0),
+            position: AtomicU64::new(0),
+        });
+        state.emit("start");
+        PhaseProgress {
+            inner,
+            state: Some(state),
+        }
+    }
+}
+
+impl<P: ProgressBars> ProgressBars for PhaseProgressBars<P> {
+    type P = PhaseProgress<P::P>;
+
+    fn progress_hidden(&self) -> Self::P {
+        PhaseProgress {
+            inner: self.0.progress_hidden(),
+            state: None,
+        }
+    }
+
+    fn progress_spinner(&self, prefix: impl Into<Cow<'static, str>>) -> Self::P {
+        let prefix = prefix.into();
+        self.phase(prefix.clone(), self.0.progress_spinner(prefix))
+    }
+
+    fn progress_counter(&self, prefix: impl Into<Cow<'static, str>>) -> Self::P {
+        let prefix = prefix.into();
+        self.phase(prefix.clone(), self.0.progress_counter(prefix))
+    }
+
+    fn progress_bytes(&self, prefix: impl Into<Cow<'static, str>>) -> Self::P {
+        let prefix = prefix.into();
+        self.phase(prefix.clone(), self.0.progress_bytes(prefix))
+    }
+}
+
+/// State of a phase
+#[derive(Debug)]
+struct PhaseState {
+    phase: Mutex<Cow<'static, str>>,
+    start: Instant,
+    length: AtomicU64,
+    position: AtomicU64,
+}
+
+/// A json event about a phase
+#[derive(Serialize)]
+struct PhaseEvent<'a> {
+    event: &'a str,
+    phase: &'a str,
+    elapsed_secs: f64,
+    length: u64,
+    position: u64,
+}
+
+impl PhaseState {
+    /// Print a json event about this phase
+    fn emit(&self, event: &str) {
+        let phase = self.phase.lock().unwrap();
+        let event = PhaseEvent {
+            event,
+            phase: &phase,
+            elapsed_secs: self.start.elapsed().as_secs_f64(),
+            length: self.length.load(Ordering::Relaxed),
+            position: self.position.load(Ordering::Relaxed),
+        };
+        match serde_json::to_string(&event) {
+            Ok(event) => println!("{event}"),
+            Err(err) => warn!("error serializing phase event: {err}"),
+        }
+    }
+}
+
+/// A progress bar of a phase, see [`PhaseProgressBars`]
+#[derive(Debug, Clone)]
+pub struct PhaseProgress<P> {
+    inner: P,
+    state: Option<Arc<PhaseState>>,
+}
+
+impl<P: Progress> Progress for PhaseProgress<P> {
+    fn is_hidden(&self) -> bool {
+        self.inner.is_hidden()
+    }
+
+    fn set_length(&self, len: u64) {
+        if let Some(state) = &self.state {
+            state.length.store(len, Ordering::Relaxed);
+        }
+        self.inner.set_length(len);
+    }
+
+    fn set_title(&self, title: &'static str) {
+        if let Some(state) = &self.state {
+            *state.phase.lock().unwrap() = title.into();
+        }
+        self.inner.set_title(title);
+    }
+
+    fn inc(&self, inc: u64) {
+        if let Some(state) = &self.state {
+            _ = state.position.fetch_add(inc, Ordering::Relaxed);
+        }
+        self.inner.inc(inc);
+    }
+
+    fn finish(&self) {
+        if let Some(state) = &self.state {
+            state.emit("finish");
+        }
+        self.inner.finish();
+    }
+}
@@ -0,0 +1,146 @@
+//! Retry Config
+
+use std::time::Duration;
+
+use clap::Parser;
+use merge::Merge;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+
+/// Retry Config
+///
+/// Retries are done for all backends, in addition to retries some backends do on their own.
+#[serde_as]
+#[derive(Default, Debug, Parser, Clone, Copy, Deserialize, Serialize, Merge)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RetryOptions {
+    /// Number of retries for failed backend operations [default: 0]
+    #[clap(long, global = true, env = "RUSTIC_RETRIES", value_name = "N")]
+    pub retries: Option<usize>,
+
+    /// Number of retries for listing files, overwrites --retries
+    #[clap(long, global = true, value_name = "N")]
+    pub retries_list: Option<usize>,
+
+    /// Number of retries for reading files, overwrites --retries
+    #[clap(long, global = true, value_name = "N")]
+    pub retries_read: Option<usize>,
+
+    /// Number of retries for writing files, overwrites --retries
+    #[clap(long, global = true, value_name = "N")]
+    pub retries_write: Option<usize>,
+
+    /// Number of retries for removing files, overwrites --retries
+    #[clap(long, global = true, value_name = "N")]
+    pub retries_remove: Option<usize>,
+
+    /// Wait time before the first retry, doubled for every further retry [default: 1s]
+    #[clap(long, global = true, value_name = "DURATION")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub retry_wait: Option<humantime::Duration>,
+
+    /// Maximum wait time between two retries [default: 60s]
+    #[clap(long, global = true, value_name = "DURATION")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub retry_max_wait: Option<humantime::Duration>,
+
+    /// Maximum number of retries in total, afterwards failed operations are not retried
+    /// [default: no limit]
+    #[clap(long, global = true, value_name = "N")]
+    pub retry_budget: Option<usize>,
+}
+
+/// Backend operations which can be configured separately
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Operation {
+    List,
+    Read,
+    Write,
+    Remove,
+}
+
+impl RetryOptions {
+    /// Check if any operation is retried
+    pub(crate) fn is_enabled(&self) -> bool {
+        [
+            Operation::List,
+            Operation::Read,
+            Operation::Write,
+            Operation::Remove,
+        ]
+        .into_iter()
+        .any(|op| self.retries_for(op) > 0)
+    }
+
+    /// Get the number of retries for the given operation
+    pub(crate) fn retries_for(&self, op: Operation) -> usize {
+        let retries = match op {
+            Operation::List => self.retries_list,
+            Operation::Read => self.retries_read,
+            Operation::Write => self.retries_write,
+            Operation::Remove => self.retries_remove,
+        };
+        retries.or(self.retries).unwrap_or(0)
+    }
+
+    /// Get the wait time before the given retry (starting with 0), without jitter
+    pub(crate) fn wait(&self, retry: usize) -> Duration {
+        let wait = self.retry_wait.map_or(Duration::from_secs(1), |d| *d);
+        let max_wait = self.retry_max_wait.map_or(Duration::from_secs(60), |d| *d);
+        let factor = 1_u32
+            .checked_shl(retry.try_into().unwrap_or(u32::MAX))
+            .unwrap_or(u32::MAX);
+        wait.saturating_mul(factor).min(max_wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(None, None, 0, 1_000)]
+    #[case(None, None, 1, 2_000)]
+    #[case(None, None, 5, 32_000)]
+    #[case(None, None, 6, 60_000)]
+    #[case(None, None, 100, 60_000)]
+    #[case(Some(100), Some(1_000), 3, 800)]
+    #[case(Some(100), Some(1_000), 4, 1_000)]
+    fn wait_doubles_up_to_max_wait(
+        #[case] wait: Option<u64>,
+        #[case] max_wait: Option<u64>,
+        #[case] retry: usize,
+        #[case] expected: u64,
+    ) {
+        let opts = RetryOptions {
+            retry_wait: wait.map(|ms| Duration::from_millis(ms).into()),
+            retry_max_wait: max_wait.map(|ms| Duration::from_millis(ms).into()),
+            ..Default::default()
+        };
+        assert_eq!(opts.wait(retry), Duration::from_millis(expected));
+    }
+
+    #[test]
+    fn retries_for_uses_operation_override() {
+        let opts = RetryOptions {
+            retries: Some(2),
+            retries_read: Some(5),
+            retries_remove: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(opts.retries_for(Operation::List), 2);
+        assert_eq!(opts.retries_for(Operation::Read), 5);
+        assert_eq!(opts.retries_for(Operation::Write), 2);
+        assert_eq!(opts.retries_for(Operation::Remove), 0);
+        assert!(opts.is_enabled());
+    }
+
+    #[test]
+    fn retries_are_disabled_by_default() {
+        let opts = RetryOptions::default();
+        assert_eq!(opts.retries_for(Operation::Read), 0);
+        assert!(!opts.is_enabled());
+    }
+}
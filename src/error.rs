@@ -26,6 +26,19 @@ pub(crate) enum RhaiErrorKinds {
     RhaiEval(#[from] Box<EvalAltResult>),
 }
 
+/// Kinds of errors when parsing a snapshot filter expression
+#[derive(Debug, Error)]
+pub(crate) enum FilterExprErrorKinds {
+    #[error("unexpected end of filter expression")]
+    UnexpectedEnd,
+    #[error("unexpected token `{0}` in filter expression")]
+    UnexpectedToken(String),
+    #[error("invalid filter `{0}`, expected KEY:VALUE with KEY one of host, label, tag, path")]
+    InvalidFilter(String),
+    #[error(transparent)]
+    Glob(#[from] globset::Error),
+}
+
 impl ErrorKind {
     /// Create an error context from this error
     pub(crate) fn context(self, source: impl Into<BoxError>) -> Context<Self> {
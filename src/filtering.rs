@@ -1,4 +1,6 @@
-use crate::error::RhaiErrorKinds;
+mod expr;
+
+use crate::{error::RhaiErrorKinds, filtering::expr::FilterExpr};
 
 use log::warn;
 use rustic_core::{repofile::SnapshotFile, StringList};
@@ -63,7 +65,8 @@ pub struct SnapshotFilter {
     #[merge(strategy=merge::vec::overwrite_empty)]
     filter_hosts: Vec<String>,
 
-    /// Label to filter (can be specified multiple times)
+    /// Label to filter (can be specified multiple times). Use "LABEL/**" to also match the labels
+    /// below LABEL, e.g. "prod/**" matches "prod", "prod/db" and "prod/db/primary"
     #[clap(long = "filter-label", global = true, value_name = "LABEL")]
     #[merge(strategy=merge::vec::overwrite_empty)]
     filter_labels: Vec<String>,
@@ -84,6 +87,11 @@ pub struct SnapshotFilter {
     #[clap(long, global = true, value_name = "FUNC")]
     #[serde_as(as = "Option<DisplayFromStr>")]
     filter_fn: Option<String>,
+
+    /// Boolean expression to filter snapshots, e.g. "tag:prod AND NOT host:dev* AND path:^/var"
+    #[clap(long, global = true, value_name = "EXPR")]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    filter_expr: Option<FilterExpr>,
 }
 
 impl SnapshotFilter {
@@ -116,9 +124,64 @@ impl SnapshotFilter {
             }
         }
 
+        if let Some(expr) = &self.filter_expr {
+            if !expr.matches(snapshot) {
+                return false;
+            }
+        }
+
         snapshot.paths.matches(&self.filter_paths)
             && snapshot.tags.matches(&self.filter_tags)
             && (self.filter_hosts.is_empty() || self.filter_hosts.contains(&snapshot.hostname))
-            && (self.filter_labels.is_empty() || self.filter_labels.contains(&snapshot.label))
+            && (self.filter_labels.is_empty()
+                || self
+                    .filter_labels
+                    .iter()
+                    .any(|label| label_matches(label, &snapshot.label)))
+    }
+}
+
+/// Check if a label matches a filter label
+///
+/// The label must be equal to the filter label. If the filter label ends with `/**`, labels below
+/// it also match, where label components are separated by `/`.
+fn label_matches(filter: &str, label: &str) -> bool {
+    let Some(parent) = filter.strip_suffix("/**") else {
+        return label == filter;
+    };
+    label
+        .strip_prefix(parent)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Only keep the latest `n` snapshots within each group of snapshots
+///
+/// # Arguments
+///
+/// * `groups` - The groups of snapshots
+/// * `n` - The number of snapshots to keep per group
+pub(crate) fn keep_latest<G>(groups: &mut [(G, Vec<SnapshotFile>)], n: usize) {
+    for (_, snapshots) in groups {
+        snapshots.sort_unstable();
+        let remove = snapshots.len().saturating_sub(n);
+        _ = snapshots.drain(..remove);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("prod", "prod", true)]
+    #[case("prod", "prod/db", false)]
+    #[case("prod/**", "prod", true)]
+    #[case("prod/**", "prod/db/primary", true)]
+    #[case("prod/db/**", "prod", false)]
+    #[case("prod/**", "production", false)]
+    fn test_label_matches(#[case] filter: &str, #[case] label: &str, #[case] expected: bool) {
+        assert_eq!(label_matches(filter, label), expected);
     }
 }
@@ -0,0 +1,218 @@
+//! Boolean filter expressions for snapshots
+//!
+//! An expression combines filters of the form `KEY:VALUE` using `AND`, `OR`, `NOT` and
+//! parentheses, e.g. `tag:prod AND NOT host:dev* AND path:^/var`. `NOT` binds strongest,
+//! then `AND`, then `OR`.
+//!
+//! `KEY` is one of `host`, `label`, `tag` or `path`. `VALUE` is a glob pattern which must
+//! match the whole value; if it starts with `^`, the rest is matched as a prefix instead.
+//! `tag` and `path` filters match if any tag or path of the snapshot matches.
+
+use std::{fmt, iter::Peekable, str::FromStr, vec::IntoIter};
+
+use globset::{Glob, GlobMatcher};
+use rustic_core::repofile::SnapshotFile;
+
+use crate::error::FilterExprErrorKinds;
+
+/// A parsed filter expression, see the module documentation for the syntax
+#[derive(Clone, Debug)]
+pub(crate) struct FilterExpr {
+    /// The expression as given by the user
+    source: String,
+    /// The parsed expression
+    expr: Expr,
+}
+
+/// A node of a filter expression
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Filter(Key, Pattern),
+}
+
+/// The snapshot field a filter applies to
+#[derive(Clone, Copy, Debug)]
+enum Key {
+    Host,
+    Label,
+    Tag,
+    Path,
+}
+
+/// A pattern to match a value against
+#[derive(Clone, Debug)]
+enum Pattern {
+    Prefix(String),
+    Glob(GlobMatcher),
+}
+
+impl Pattern {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Prefix(prefix) => value.starts_with(prefix.as_str()),
+            Self::Glob(glob) => glob.is_match(value),
+        }
+    }
+}
+
+impl Expr {
+    fn matches(&self, sn: &SnapshotFile) -> bool {
+        match self {
+            Self::And(left, right) => left.matches(sn) && right.matches(sn),
+            Self::Or(left, right) => left.matches(sn) || right.matches(sn),
+            Self::Not(expr) => !expr.matches(sn),
+            Self::Filter(Key::Host, pattern) => pattern.matches(&sn.hostname),
+            Self::Filter(Key::Label, pattern) => pattern.matches(&sn.label),
+            Self::Filter(Key::Tag, pattern) => sn.tags.iter().any(|tag| pattern.matches(tag)),
+            Self::Filter(Key::Path, pattern) => sn.paths.iter().any(|path| pattern.matches(path)),
+        }
+    }
+}
+
+impl FilterExpr {
+    /// Check if a [`SnapshotFile`] matches the expression
+    pub(crate) fn matches(&self, sn: &SnapshotFile) -> bool {
+        self.expr.matches(sn)
+    }
+}
+
+impl fmt::Display for FilterExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for FilterExpr {
+    type Err = FilterExprErrorKinds;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(s).into_iter().peekable();
+        let expr = parse_or(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            return Err(FilterExprErrorKinds::UnexpectedToken(token));
+        }
+        Ok(Self {
+            source: s.to_string(),
+            expr,
+        })
+    }
+}
+
+type Tokens = Peekable<IntoIter<String>>;
+
+/// Split an expression into words and parentheses
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    for c in s.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+fn parse_or(tokens: &mut Tokens) -> Result<Expr, FilterExprErrorKinds> {
+    let mut expr = parse_and(tokens)?;
+    while tokens.next_if(|t| t == "OR").is_some() {
+        expr = Expr::Or(Box::new(expr), Box::new(parse_and(tokens)?));
+    }
+    Ok(expr)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Expr, FilterExprErrorKinds> {
+    let mut expr = parse_not(tokens)?;
+    while tokens.next_if(|t| t == "AND").is_some() {
+        expr = Expr::And(Box::new(expr), Box::new(parse_not(tokens)?));
+    }
+    Ok(expr)
+}
+
+fn parse_not(tokens: &mut Tokens) -> Result<Expr, FilterExprErrorKinds> {
+    let token = tokens.next().ok_or(FilterExprErrorKinds::UnexpectedEnd)?;
+    match token.as_str() {
+        "NOT" => Ok(Expr::Not(Box::new(parse_not(tokens)?))),
+        "(" => {
+            let expr = parse_or(tokens)?;
+            match tokens.next() {
+                Some(t) if t == ")" => Ok(expr),
+                Some(t) => Err(FilterExprErrorKinds::UnexpectedToken(t)),
+                None => Err(FilterExprErrorKinds::UnexpectedEnd),
+            }
+        }
+        "AND" | "OR" | ")" => Err(FilterExprErrorKinds::UnexpectedToken(token)),
+        _ => parse_filter(token),
+    }
+}
+
+fn parse_filter(token: String) -> Result<Expr, FilterExprErrorKinds> {
+    let Some((key, value)) = token.split_once(':') else {
+        return Err(FilterExprErrorKinds::InvalidFilter(token));
+    };
+    let key = match key {
+        "host" => Key::Host,
+        "label" => Key::Label,
+        "tag" => Key::Tag,
+        "path" => Key::Path,
+        _ => return Err(FilterExprErrorKinds::InvalidFilter(token)),
+    };
+    let pattern = match value.strip_prefix('^') {
+        Some(prefix) => Pattern::Prefix(prefix.to_string()),
+        None => Pattern::Glob(Glob::new(value)?.compile_matcher()),
+    };
+    Ok(Expr::Filter(key, pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use rustic_core::StringList;
+
+    fn snapshot() -> SnapshotFile {
+        SnapshotFile {
+            hostname: "devbox".to_string(),
+            label: "daily".to_string(),
+            tags: StringList::from_str("prod,db").unwrap(),
+            paths: StringList::from_str("/var/lib,/etc").unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case("tag:prod", true)]
+    #[case("tag:prod AND NOT host:dev*", false)]
+    #[case("tag:prod AND path:^/var", true)]
+    #[case("label:weekly OR tag:db", true)]
+    #[case("NOT (label:daily OR host:other)", false)]
+    #[case("path:/var", false)]
+    fn test_filter_expr_matches(#[case] expr: &str, #[case] expected: bool) {
+        let expr: FilterExpr = expr.parse().unwrap();
+        assert_eq!(expr.matches(&snapshot()), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("tag:prod AND")]
+    #[case("(tag:prod")]
+    #[case("tag:prod)")]
+    #[case("owner:me")]
+    #[case("prod")]
+    fn test_filter_expr_invalid(#[case] expr: &str) {
+        assert!(expr.parse::<FilterExpr>().is_err());
+    }
+}
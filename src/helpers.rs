@@ -1,7 +1,13 @@
+use std::time::{Duration, Instant};
+
 use bytesize::ByteSize;
 use comfy_table::{
     presets::ASCII_MARKDOWN, Attribute, Cell, CellAlignment, ContentArrangement, Table,
 };
+use humantime::format_duration;
+use log::info;
+use serde::Serialize;
+use serde_with::{serde_as, DurationSecondsWithFrac};
 
 /// Helpers for table output
 
@@ -46,3 +52,78 @@ pub fn table_right_from<I: IntoIterator<Item = T>, T: ToString>(start: usize, ti
 pub fn bytes_size_to_string(b: u64) -> String {
     ByteSize(b).to_string_as(true)
 }
+
+/// Get the peak resident memory size and the used CPU time (user + system) of this process
+#[cfg(not(windows))]
+fn rusage() -> Option<(u64, Duration)> {
+    use nix::sys::{
+        resource::{getrusage, UsageWho},
+        time::TimeValLike,
+    };
+
+    let usage = getrusage(UsageWho::RUSAGE_SELF).ok()?;
+    // the maximum resident set size is given in bytes on macOS and in kilobytes elsewhere
+    let unit = if cfg!(any(target_os = "macos", target_os = "ios")) {
+        1
+    } else {
+        1024
+    };
+    let peak_rss = u64::try_from(usage.max_rss()).ok()? * unit;
+    let cpu_micros = usage.user_time().num_microseconds() + usage.system_time().num_microseconds();
+    Some((
+        peak_rss,
+        Duration::from_micros(u64::try_from(cpu_micros).ok()?),
+    ))
+}
+
+/// Get the peak resident memory size and the used CPU time (user + system) of this process
+#[cfg(windows)]
+fn rusage() -> Option<(u64, Duration)> {
+    None
+}
+
+/// Resources used by this process
+#[serde_as]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ResourceUsage {
+    /// Peak resident memory size in bytes, not available on Windows
+    peak_rss: Option<u64>,
+    /// Used CPU time (user + system) in seconds, not available on Windows
+    #[serde_as(as = "Option<DurationSecondsWithFrac<f64>>")]
+    cpu_time: Option<Duration>,
+    /// Wall time in seconds
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    wall_time: Duration,
+}
+
+impl ResourceUsage {
+    /// Get the resources used by this process, the wall time is measured since `start`
+    #[must_use]
+    pub fn since(start: Instant) -> Self {
+        let usage = rusage();
+        Self {
+            peak_rss: usage.map(|(peak_rss, _)| peak_rss),
+            cpu_time: usage.map(|(_, cpu)| cpu),
+            wall_time: start.elapsed(),
+        }
+    }
+
+    /// Log the resource usage
+    pub fn log(&self) {
+        // only show full seconds for the wall time
+        let wall = format_duration(Duration::from_secs(self.wall_time.as_secs()));
+        match (self.peak_rss, self.cpu_time) {
+            (Some(peak_rss), Some(cpu)) => info!(
+                "resources: peak memory {}, cpu time {}, wall time {wall}",
+                bytes_size_to_string(peak_rss),
+                format_duration(cpu)
+            ),
+            _ => info!("resources: wall time {wall}"),
+        }
+    }
+}
+
+/// Log the peak memory usage, the used CPU time and the wall time since `start`
+pub fn log_resource_usage(start: Instant) {
+    ResourceUsage::since(start).log();
+}
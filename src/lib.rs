@@ -57,11 +57,13 @@ Application based on the [Abscissa] framework.
 )]
 
 pub mod application;
+pub(crate) mod backend;
 pub(crate) mod commands;
 pub(crate) mod config;
 pub(crate) mod error;
 pub(crate) mod filtering;
 pub(crate) mod helpers;
+pub(crate) mod systemd;
 
 // rustic_cli Public API
 
@@ -0,0 +1,292 @@
+//! Support for running as a systemd service
+//!
+//! If rustic runs as a systemd service with `Type=notify`, `NOTIFY_SOCKET` is set and long-running
+//! commands report their readiness to it. If `WatchdogSec=` is configured, watchdog keep-alive
+//! messages are sent as well, see `sd_notify(3)`. They are sent from the loops doing the actual
+//! work, such that a hanging process is detected. Without systemd, nothing is done.
+//!
+//! `serve` and `webdav` accept a listening socket passed by socket activation, see
+//! `sd_listen_fds(3)`. If stderr is connected to the journal, log messages are sent to the
+//! journal directly, such that they get their priority and source location as structured fields.
+
+use std::{
+    env,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn, LevelFilter};
+#[cfg(unix)]
+use log::{Level, Log, Metadata, Record};
+use simplelog::SharedLogger;
+
+/// Send the given state to the service manager, if there is one
+///
+/// Errors are only logged, as a failing notification must not stop the command.
+///
+/// # Arguments
+///
+/// * `state` - The newline-separated state assignments, e.g. `READY=1`
+pub(crate) fn notify(state: &str) {
+    let Some(socket) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    debug!("notifying service manager: {state}");
+    if let Err(err) = send(&socket, state) {
+        warn!("error notifying service manager: {err}");
+    }
+}
+
+#[cfg(unix)]
+fn send(socket: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::{ffi::OsStrExt, net::UnixDatagram};
+
+    let sock = UnixDatagram::unbound()?;
+    match socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+            let addr = SocketAddr::from_abstract_name(name)?;
+            _ = sock.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "abstract sockets are not supported",
+            ))
+        }
+        None => {
+            _ = sock.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &std::ffi::OsStr, _state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Get the interval in which watchdog keep-alive messages must be sent, if the watchdog is enabled
+pub(crate) fn watchdog_interval() -> Option<Duration> {
+    static INTERVAL: OnceLock<Option<Duration>> = OnceLock::new();
+    *INTERVAL.get_or_init(|| {
+        // the watchdog may be meant for another process
+        if let Ok(pid) = env::var("WATCHDOG_PID") {
+            if pid.parse::<u32>().ok() != Some(std::process::id()) {
+                return None;
+            }
+        }
+        let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+        // notify twice per interval as recommended by systemd
+        (usec > 0).then_some(Duration::from_micros(usec / 2))
+    })
+}
+
+/// Send a watchdog keep-alive message, if the watchdog is enabled
+///
+/// This is called whenever work is done, so messages are sent at most once per interval.
+pub(crate) fn keep_alive() {
+    static LAST: Mutex<Option<Instant>> = Mutex::new(None);
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    {
+        let mut last = LAST.lock().unwrap();
+        if last.is_some_and(|last| last.elapsed() < interval) {
+            return;
+        }
+        *last = Some(Instant::now());
+    }
+    notify("WATCHDOG=1");
+}
+
+/// Send watchdog keep-alive messages as long as the async runtime is responsive
+#[cfg(any(feature = "serve", feature = "webdav"))]
+pub(crate) async fn keep_alive_task() {
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    loop {
+        keep_alive();
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Report readiness to the service manager
+///
+/// # Arguments
+///
+/// * `status` - A human-readable status to report
+pub(crate) fn ready(status: &str) {
+    notify(&format!("READY=1\nSTATUS={status}"));
+    keep_alive();
+}
+
+/// Take the listening socket passed by the service manager, if rustic was socket activated
+///
+/// Only the first socket is used; it must be a TCP socket.
+#[cfg(any(feature = "serve", feature = "webdav"))]
+pub(crate) fn listener() -> std::io::Result<Option<std::net::TcpListener>> {
+    let listener = listenfd::ListenFd::from_env().take_tcp_listener(0)?;
+    if let Some(listener) = &listener {
+        debug!(
+            "using socket {:?} passed by the service manager",
+            listener.local_addr()
+        );
+        // needed to use the socket with tokio
+        listener.set_nonblocking(true)?;
+    }
+    Ok(listener)
+}
+
+/// Get a logger sending log messages to the journal, if stderr is connected to the journal
+///
+/// # Arguments
+///
+/// * `level` - The maximum level of messages to log
+#[cfg(unix)]
+pub(crate) fn journal_logger(level: LevelFilter) -> Option<Box<dyn SharedLogger>> {
+    use std::os::unix::net::UnixDatagram;
+
+    if !stderr_is_journal() {
+        return None;
+    }
+    let socket = UnixDatagram::unbound()
+        .and_then(|socket| socket.connect(JOURNAL_SOCKET).map(|()| socket))
+        .ok()?;
+    Some(Box::new(JournalLogger { level, socket }))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn journal_logger(_level: LevelFilter) -> Option<Box<dyn SharedLogger>> {
+    None
+}
+
+/// Path of the socket of the native journal protocol
+#[cfg(unix)]
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Check if stderr is connected to the journal
+///
+/// `JOURNAL_STREAM` contains device and inode of the stream, which are compared to those of stderr
+/// to ignore the variable if it was inherited by a process with redirected stderr.
+#[cfg(unix)]
+fn stderr_is_journal() -> bool {
+    use std::{
+        fs::File,
+        os::{fd::AsFd, unix::fs::MetadataExt},
+    };
+
+    let Ok(stream) = env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Some((Ok(dev), Ok(ino))) = stream
+        .split_once(':')
+        .map(|(dev, ino)| (dev.parse::<u64>(), ino.parse::<u64>()))
+    else {
+        return false;
+    };
+    std::io::stderr()
+        .as_fd()
+        .try_clone_to_owned()
+        .map(File::from)
+        .and_then(|file| file.metadata())
+        .is_ok_and(|meta| meta.dev() == dev && meta.ino() == ino)
+}
+
+/// A logger sending log messages to the journal using its native protocol
+#[cfg(unix)]
+struct JournalLogger {
+    /// The maximum level of messages to log
+    level: LevelFilter,
+    /// The socket connected to the journal
+    socket: std::os::unix::net::UnixDatagram,
+}
+
+/// Append a field to a message of the native journal protocol
+///
+/// Values containing a newline are length-prefixed as required by the protocol.
+#[cfg(unix)]
+fn append_field(msg: &mut Vec<u8>, name: &str, value: &str) {
+    msg.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        msg.push(b'\n');
+        msg.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        msg.push(b'=');
+    }
+    msg.extend_from_slice(value.as_bytes());
+    msg.push(b'\n');
+}
+
+#[cfg(unix)]
+impl Log for JournalLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        // see syslog(3) for the priorities
+        let priority = match record.level() {
+            Level::Error => "3",
+            Level::Warn => "4",
+            Level::Info => "6",
+            Level::Debug | Level::Trace => "7",
+        };
+        let mut msg = Vec::new();
+        append_field(&mut msg, "MESSAGE", &message);
+        append_field(&mut msg, "PRIORITY", priority);
+        append_field(&mut msg, "SYSLOG_IDENTIFIER", "rustic");
+        append_field(&mut msg, "TARGET", record.target());
+        if let Some(file) = record.file() {
+            append_field(&mut msg, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            append_field(&mut msg, "CODE_LINE", &line.to_string());
+        }
+        // e.g. messages which are too large for a datagram
+        if self.socket.send(&msg).is_err() {
+            eprintln!("{}: {message}", record.level());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(unix)]
+impl SharedLogger for JournalLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&simplelog::Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_field_encodes_newlines() {
+        let mut msg = Vec::new();
+        append_field(&mut msg, "PRIORITY", "6");
+        append_field(&mut msg, "MESSAGE", "a\nb");
+
+        let mut expected = b"PRIORITY=6\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&3_u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(msg, expected);
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! Runs the application as a subprocess and asserts its
 //! output for the `init`, `backup`, `restore`, `check`,
-//! and `snapshots` command
+//! `snapshots`, `index-paths` and `find` command
 //!
 //! You can run them with 'nextest':
 //! `cargo nextest run -E 'test(backup)'`.
@@ -138,3 +138,46 @@ fn test_backup_and_restore_passes() -> TestResult<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_backup_and_find_from_index_passes() -> TestResult<()> {
+    let temp_dir = setup()?;
+    let cache_dir = temp_dir.path().join("cache");
+    let backup_files = std::env::current_dir()?.join("src/");
+
+    {
+        // Run `backup`
+        rustic_runner(&temp_dir)?
+            .arg("backup")
+            .arg(&backup_files)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("successfully saved."));
+    }
+    {
+        // Run `index-paths build`
+        rustic_runner(&temp_dir)?
+            .arg("--cache-dir")
+            .arg(&cache_dir)
+            .args(["index-paths", "build"])
+            .assert()
+            .success();
+    }
+    {
+        // Run `find --from-index` for a nested path
+        rustic_runner(&temp_dir)?
+            .arg("--cache-dir")
+            .arg(&cache_dir)
+            .args([
+                "find",
+                "--from-index",
+                "--glob",
+                "**/commands/index_paths.rs",
+            ])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("src/commands/index_paths.rs"));
+    }
+
+    Ok(())
+}